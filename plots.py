@@ -1,16 +1,17 @@
 from collections import OrderedDict
+import os
 
 import pandas as pd
 import matplotlib.pyplot as plt
 
-data = pd.read_csv("ising.txt", sep="\s+")
+data = pd.read_csv("ising.txt", sep="\s+", comment="#")
 
 data_grouped = OrderedDict(
     (l, data[(data["l"] == l)].sort_values("t")) for l in [6, 15, 40, 70]
 )
 markers = ["-s", "-^", "-o", "-+"]
 for (l, d), marker in zip(data_grouped.items(), markers):
-    plt.plot(d["t"], d["m"], marker, label=f"L={l}", ms=5)
+    plt.errorbar(d["t"], d["m"], d["dm"], fmt=marker, label=f"L={l}", ms=5)
 
 plt.legend()
 plt.xlabel("T*")
@@ -18,10 +19,33 @@ plt.ylabel(r"$\langle m \rangle $")
 plt.show()
 
 for (l, d), marker in zip(list(data_grouped.items())[:-1], markers):
-    plt.plot(d["t"], d["s"], marker, label=f"L={l}", ms=5)
+    plt.errorbar(d["t"], d["s"], d["ds"], fmt=marker, label=f"L={l}", ms=5)
 
 plt.legend()
 plt.xlabel("T*")
 plt.ylabel(r"$\chi$")
 
 plt.show()
+
+for (l, d), marker in zip(data_grouped.items(), markers):
+    plt.errorbar(d["t"], d["c"], d["dc"], fmt=marker, label=f"L={l}", ms=5)
+
+plt.legend()
+plt.xlabel("T*")
+plt.ylabel(r"$C$")
+
+plt.show()
+
+# data collapse written by `ising scaling ising.txt`
+if os.path.exists("scaling.txt"):
+    collapse = pd.read_csv("scaling.txt", sep="\s+", comment="#")
+    for column, label in [("m", r"$m L^{\beta/\nu}$"), ("s", r"$\chi L^{-\gamma/\nu}$")]:
+        for (l, d), marker in zip(collapse.groupby("l"), markers):
+            d = d.sort_values("x")
+            plt.errorbar(d["x"], d[column], d["d" + column], fmt=marker, label=f"L={l}", ms=5)
+
+        plt.legend()
+        plt.xlabel(r"$(T - T_c) L^{1/\nu}$")
+        plt.ylabel(label)
+
+        plt.show()
@@ -0,0 +1,100 @@
+use std::time::{Duration, Instant};
+
+use rand::{Rng, SeedableRng};
+
+use crate::lattice::{get_neighbour_table, Lattice};
+use crate::metropolis::{
+    get_acceptance_array, get_acceptance_thresholds, get_trans_map, recalc_lattice,
+    recalc_lattice_with_table, recalc_lattice_with_thresholds,
+};
+use crate::rng::{RngKind, WithRng};
+
+/// Timing of the single-spin Metropolis sweep with each way of deciding a flip.
+pub struct Benchmark {
+    pub lattice_size: usize,
+    pub temperature: f64,
+    pub sweeps: u32,
+    pub seed: u64,
+    pub rng: RngKind,
+}
+
+/// Time taken by a single implementation.
+pub struct Timing {
+    pub implementation: &'static str,
+    pub elapsed: Duration,
+    /// Whether the final lattice equals the one of the reference implementation.
+    pub identical: bool,
+}
+
+impl Timing {
+    /// Average time spent on a single spin visit, in nanoseconds.
+    pub fn nanos_per_spin(&self, benchmark: &Benchmark) -> f64 {
+        let visits = benchmark.sweeps as f64 * benchmark.lattice_size.pow(2) as f64;
+        self.elapsed.as_nanos() as f64 / visits
+    }
+}
+
+impl Benchmark {
+    /// Times the implementations, starting each from the same lattice and random stream.
+    ///
+    /// The first timing is the reference implementation, [`recalc_lattice`].
+    pub fn run(&self) -> Vec<Timing> {
+        self.rng.dispatch(self)
+    }
+
+    /// Same as [`Benchmark::run`], drawing random numbers from generators of the given type.
+    pub fn run_with<R: Rng + SeedableRng>(&self) -> Vec<Timing> {
+        let trans_map = get_trans_map(self.temperature);
+        let neighbours = get_neighbour_table(self.lattice_size);
+        let acceptance = get_acceptance_array(&trans_map);
+        let thresholds = get_acceptance_thresholds(&acceptance);
+
+        let (reference, elapsed) = self.time::<R, _>(|lattice, rng| {
+            recalc_lattice(lattice, &trans_map, rng);
+        });
+        let mut timings = vec![Timing {
+            implementation: "hash-map",
+            elapsed,
+            identical: true,
+        }];
+        let (lattice, elapsed) = self.time::<R, _>(|lattice, rng| {
+            recalc_lattice_with_table(lattice, &neighbours, &acceptance, rng);
+        });
+        timings.push(Timing {
+            implementation: "float-array",
+            elapsed,
+            identical: lattice == reference,
+        });
+        let (lattice, elapsed) = self.time::<R, _>(|lattice, rng| {
+            recalc_lattice_with_thresholds(lattice, &neighbours, &thresholds, rng);
+        });
+        timings.push(Timing {
+            implementation: "integer-threshold",
+            elapsed,
+            identical: lattice == reference,
+        });
+        timings
+    }
+
+    fn time<R, F>(&self, mut sweep: F) -> (Lattice, Duration)
+    where
+        R: Rng + SeedableRng,
+        F: FnMut(&mut Lattice, &mut R),
+    {
+        let mut rng = R::seed_from_u64(self.seed);
+        let mut lattice = Lattice::random(self.lattice_size, &mut rng);
+        let started = Instant::now();
+        for _ in 0..self.sweeps {
+            sweep(&mut lattice, &mut rng);
+        }
+        (lattice, started.elapsed())
+    }
+}
+
+impl WithRng for &Benchmark {
+    type Output = Vec<Timing>;
+
+    fn run<R: Rng + SeedableRng + Send>(self) -> Vec<Timing> {
+        self.run_with::<R>()
+    }
+}
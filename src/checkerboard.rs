@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+
+use crate::lattice::Lattice;
+use crate::metropolis::get_trans_map;
+use crate::updater::Update;
+
+/// Metropolis updates parallelized over a red/black decomposition of the lattice.
+///
+/// Sites of one colour only neighbour sites of the other colour, so a whole sublattice
+/// can be updated at once. Every row draws from its own random stream, which keeps the
+/// results independent of the number of threads. The lattice size must be even.
+pub struct Checkerboard<S> {
+    trans_map: HashMap<i8, f64>,
+    row_rngs: Vec<S>,
+    flips: Vec<bool>,
+}
+
+impl<S: Rng + SeedableRng + Send> Checkerboard<S> {
+    /// Creates the updater for lattices of the given size,
+    /// seeding the per-row random streams from `rng`.
+    ///
+    /// Callers check that the size is even; [`crate::updater::AnyUpdater::new`] fails otherwise.
+    pub fn new<R: Rng + ?Sized>(temperature: f64, lattice_size: usize, rng: &mut R) -> Self {
+        assert!(
+            lattice_size.is_multiple_of(2),
+            "checkerboard updates need an even lattice size"
+        );
+        Checkerboard {
+            trans_map: get_trans_map(temperature),
+            row_rngs: (0..lattice_size)
+                .map(|_| S::seed_from_u64(rng.gen()))
+                .collect(),
+            flips: vec![false; lattice_size * lattice_size],
+        }
+    }
+
+    /// Updates every site whose row and column indices sum to a number of the given parity,
+    /// returning the change of the total energy.
+    fn update_sublattice(&mut self, lattice: &mut Lattice, parity: usize) -> i64 {
+        let size = lattice.size();
+        let trans_map = &self.trans_map;
+        let frozen: &Lattice = lattice;
+        // sites of one colour do not interact, so their energy changes simply add up
+        let total_change = self
+            .flips
+            .par_chunks_mut(size)
+            .zip(self.row_rngs.par_iter_mut())
+            .enumerate()
+            .map(|(row, (row_flips, rng))| {
+                let mut row_change = 0;
+                for (column, flip) in row_flips.iter_mut().enumerate() {
+                    let index = row * size + column;
+                    *flip = (row + column) % 2 == parity && {
+                        let energy_change = 2 * frozen.spins()[index] * frozen.neighbour_sum(index);
+                        let accepted = rng.gen_bool(trans_map[&energy_change]);
+                        if accepted {
+                            row_change += energy_change as i64;
+                        }
+                        accepted
+                    };
+                }
+                row_change
+            })
+            .sum();
+
+        let flips = &self.flips;
+        lattice
+            .spins_mut()
+            .par_iter_mut()
+            .zip(flips.par_iter())
+            .for_each(|(spin, flip)| {
+                if *flip {
+                    *spin = -*spin;
+                }
+            });
+        total_change
+    }
+}
+
+impl<S: Rng + SeedableRng + Send> Update for Checkerboard<S> {
+    /// Updates the even sublattice, then the odd one.
+    fn sweep<R: Rng + ?Sized>(&mut self, lattice: &mut Lattice, _rng: &mut R) -> i64 {
+        self.update_sublattice(lattice, 0) + self.update_sublattice(lattice, 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sweep::tests::assert_agrees_with_metropolis;
+    use crate::updater::Updater;
+
+    #[test]
+    fn agrees_with_metropolis() {
+        assert_agrees_with_metropolis(Updater::Checkerboard);
+    }
+}
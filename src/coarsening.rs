@@ -0,0 +1,104 @@
+use rand::{Rng, SeedableRng};
+
+use crate::kawasaki::Kawasaki;
+use crate::lattice::Lattice;
+use crate::observables::{
+    get_broken_bond_density, get_domain_size, get_structure_factor, get_structure_factor_length,
+};
+use crate::rng::{RngKind, WithRng};
+use crate::updater::Update;
+
+/// Quench of a random configuration with fixed magnetization to a temperature below Tc,
+/// followed by phase separation under Kawasaki dynamics.
+pub struct Coarsening {
+    pub lattice_size: usize,
+    pub temperature: f64,
+    pub magnetization: f64,
+    pub steps: u32,
+    /// Number of (logarithmically spaced) measurement times.
+    pub measurements: u32,
+    pub seed: u64,
+    pub rng: RngKind,
+}
+
+/// Domain growth observables measured at a single time.
+pub struct CoarseningRecord {
+    pub step: u32,
+    pub broken_bond_density: f64,
+    /// Domain size from the density of domain walls.
+    pub domain_size: f64,
+    /// Domain size from the first moment of the structure factor.
+    pub structure_factor_length: f64,
+}
+
+impl Coarsening {
+    /// Settings recorded in the header of the output files.
+    pub fn metadata(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("seed", self.seed.to_string()),
+            ("rng", self.rng.to_string()),
+            ("updater", String::from("kawasaki")),
+            ("lattice_size", self.lattice_size.to_string()),
+            ("temperature", self.temperature.to_string()),
+            ("magnetization", self.magnetization.to_string()),
+            ("steps", self.steps.to_string()),
+        ]
+    }
+
+    /// Sweeps after which measurements are taken, roughly evenly spaced in `log(t)`.
+    /// The last measurement is always taken after all `steps` sweeps.
+    pub fn measurement_steps(&self) -> Vec<u32> {
+        let last = self.measurements.saturating_sub(1);
+        let ratio = (self.steps as f64).ln() / last.max(1) as f64;
+        let mut steps: Vec<u32> = (0..self.measurements)
+            .map(|i| {
+                if i == last {
+                    self.steps
+                } else {
+                    (ratio * i as f64).exp().round() as u32
+                }
+            })
+            .collect();
+        steps.dedup();
+        steps
+    }
+
+    /// Runs the quench, returning the time series and the final structure factor.
+    pub fn run(&self) -> (Vec<CoarseningRecord>, Vec<(f64, f64)>) {
+        self.rng.dispatch(self)
+    }
+
+    /// Same as [`Coarsening::run`], drawing random numbers from the given generator.
+    pub fn run_with<R: Rng>(&self, rng: &mut R) -> (Vec<CoarseningRecord>, Vec<(f64, f64)>) {
+        let mut lattice = Lattice::with_magnetization(self.lattice_size, self.magnetization, rng);
+        let mut updater = Kawasaki::new(self.temperature);
+        let mut step = 0;
+        let records = self
+            .measurement_steps()
+            .into_iter()
+            .map(|measurement_step| {
+                while step < measurement_step {
+                    updater.sweep(&mut lattice, rng);
+                    step += 1;
+                }
+                CoarseningRecord {
+                    step,
+                    broken_bond_density: get_broken_bond_density(&lattice),
+                    domain_size: get_domain_size(&lattice),
+                    structure_factor_length: get_structure_factor_length(&get_structure_factor(
+                        &lattice,
+                    )),
+                }
+            })
+            .collect();
+        (records, get_structure_factor(&lattice))
+    }
+}
+
+impl WithRng for &Coarsening {
+    type Output = (Vec<CoarseningRecord>, Vec<(f64, f64)>);
+
+    fn run<R: Rng + SeedableRng + Send>(self) -> Self::Output {
+        self.run_with(&mut R::seed_from_u64(self.seed))
+    }
+}
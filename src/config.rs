@@ -0,0 +1,416 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::equilibration::FIRST_CHECK;
+use crate::refinement::RefinementObservable;
+use crate::results::Record;
+use crate::rng::RngKind;
+use crate::statistics::ErrorMethod;
+use crate::updater::Updater;
+
+pub const DEFAULT_INITIAL_STEPS: u32 = 30_000;
+pub const DEFAULT_LATER_STEPS: u32 = 200_000;
+pub const DEFAULT_MAGN_CALC_STEP: u32 = 100;
+pub const DEFAULT_LATTICE_SIZES: [usize; 4] = [6, 15, 40, 70];
+pub const DEFAULT_REFINE_POINTS: u32 = 4;
+
+/// Fully resolved description of a single sweep over lattice sizes and temperatures.
+pub struct Sweep {
+    pub sizes: Vec<usize>,
+    pub temperatures: Vec<f64>,
+    /// Sweeps discarded before measuring, or the most that may be discarded
+    /// with `auto_equilibration`.
+    pub initial_steps: u32,
+    /// Whether thermalization ends as soon as the energy stops drifting.
+    pub auto_equilibration: bool,
+    /// Sweeps performed while measuring, or the most that may be performed with `target_error`.
+    pub later_steps: u32,
+    pub magn_calc_step: u32,
+    /// Relative error of `target_observable` at which measuring stops.
+    pub target_error: Option<f64>,
+    pub target_observable: TargetObservable,
+    /// Master seed every per-simulation random stream is derived from.
+    pub seed: u64,
+    pub rng: RngKind,
+    pub updater: Updater,
+    /// Magnetization per spin of the initial lattice; spins are independent when omitted.
+    pub initial_magnetization: Option<f64>,
+    /// Sweeps between replica-exchange attempts; simulations are independent when omitted.
+    pub swap_interval: Option<u32>,
+    pub error_method: ErrorMethod,
+    /// Rounds of adding temperatures where `refine_observable` peaks or changes fastest.
+    pub refine_rounds: u32,
+    /// Temperatures added per lattice size and round.
+    pub refine_points: u32,
+    pub refine_observable: RefinementObservable,
+    /// Whether the measurements of every simulation are saved for reweighting.
+    pub save_series: bool,
+}
+
+impl Sweep {
+    /// Settings recorded in the header of the results file.
+    pub fn metadata(&self) -> Vec<(&'static str, String)> {
+        let mut metadata = vec![
+            ("seed", self.seed.to_string()),
+            ("rng", self.rng.to_string()),
+            ("updater", self.updater.to_string()),
+            ("initial_steps", self.initial_steps.to_string()),
+            ("auto_equilibration", self.auto_equilibration.to_string()),
+            ("later_steps", self.later_steps.to_string()),
+            ("magn_calc_step", self.magn_calc_step.to_string()),
+            ("error_method", self.error_method.to_string()),
+        ];
+        if let Some(target_error) = self.target_error {
+            metadata.push(("target_error", target_error.to_string()));
+            metadata.push(("target_observable", self.target_observable.to_string()));
+        }
+        if self.refine_rounds > 0 {
+            metadata.push(("refine_rounds", self.refine_rounds.to_string()));
+            metadata.push(("refine_points", self.refine_points.to_string()));
+            metadata.push(("refine_observable", self.refine_observable.to_string()));
+        }
+        if let Some(swap_interval) = self.swap_interval {
+            metadata.push(("swap_interval", swap_interval.to_string()));
+        }
+        if let Some(magnetization) = self.initial_magnetization {
+            metadata.push(("initial_magnetization", magnetization.to_string()));
+        }
+        metadata
+    }
+}
+
+/// Observable whose precision decides when a simulation has measured enough.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TargetObservable {
+    Magnetization,
+    #[default]
+    Susceptibility,
+}
+
+impl TargetObservable {
+    /// Relative error of the observable in the record.
+    pub fn relative_error(&self, record: &Record) -> f64 {
+        match self {
+            TargetObservable::Magnetization => {
+                record.magnetization_error / record.magnetization.abs()
+            }
+            TargetObservable::Susceptibility => {
+                record.susceptibility_error / record.susceptibility.abs()
+            }
+        }
+    }
+}
+
+impl fmt::Display for TargetObservable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.to_possible_value().unwrap().get_name())
+    }
+}
+
+/// Experiment file holding any number of named sweep definitions.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Experiment {
+    pub sweeps: BTreeMap<String, SweepDefinition>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SweepDefinition {
+    #[serde(default = "default_lattice_sizes")]
+    pub sizes: Vec<usize>,
+    pub temperatures: TemperatureGrid,
+    #[serde(default = "default_initial_steps")]
+    pub initial_steps: u32,
+    #[serde(default)]
+    pub auto_equilibration: bool,
+    #[serde(default = "default_later_steps")]
+    pub later_steps: u32,
+    #[serde(default = "default_magn_calc_step")]
+    pub magn_calc_step: u32,
+    pub target_error: Option<f64>,
+    #[serde(default)]
+    pub target_observable: TargetObservable,
+    /// Master seed of the sweep; a random one is drawn when omitted.
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub rng: RngKind,
+    #[serde(default)]
+    pub updater: Updater,
+    pub initial_magnetization: Option<f64>,
+    pub swap_interval: Option<u32>,
+    #[serde(default)]
+    pub error_method: ErrorMethod,
+    #[serde(default)]
+    pub refine_rounds: u32,
+    #[serde(default = "default_refine_points")]
+    pub refine_points: u32,
+    #[serde(default)]
+    pub refine_observable: RefinementObservable,
+    #[serde(default)]
+    pub save_series: bool,
+    /// Directory the results and a copy of the experiment file are written to.
+    pub output: PathBuf,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum TemperatureGrid {
+    /// Evenly spaced temperatures from `min` (inclusive) to `max` (exclusive).
+    Linear { min: f64, max: f64, step: f64 },
+    /// `count` logarithmically spaced temperatures from `min` to `max` (both inclusive).
+    Log { min: f64, max: f64, count: usize },
+    /// Explicit list of temperatures.
+    List(Vec<f64>),
+}
+
+fn default_lattice_sizes() -> Vec<usize> {
+    DEFAULT_LATTICE_SIZES.to_vec()
+}
+
+fn default_refine_points() -> u32 {
+    DEFAULT_REFINE_POINTS
+}
+
+fn default_initial_steps() -> u32 {
+    DEFAULT_INITIAL_STEPS
+}
+
+fn default_later_steps() -> u32 {
+    DEFAULT_LATER_STEPS
+}
+
+fn default_magn_calc_step() -> u32 {
+    DEFAULT_MAGN_CALC_STEP
+}
+
+/// Temperatures `start, start + step, ...` below `end`, for `0 < start < end` and `step > 0`.
+pub fn get_float_range(start: f64, end: f64, step: f64) -> Vec<f64> {
+    // the tolerance keeps `end` out when rounding puts `(end - start) / step` just above an integer
+    let count = ((end - start) / step - 1e-9).ceil().max(0.0) as usize;
+    (0..count).map(|i| start + i as f64 * step).collect()
+}
+
+fn get_log_range(start: f64, end: f64, count: usize) -> Vec<f64> {
+    let ratio = (end / start).ln() / (count - 1) as f64;
+    (0..count)
+        .map(|i| start * (ratio * i as f64).exp())
+        .collect()
+}
+
+impl Experiment {
+    /// Reads an experiment from a `.toml` or `.json` file and validates every sweep in it.
+    pub fn load(path: &Path) -> Result<Experiment, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|error| format!("cannot read {}: {}", path.display(), error))?;
+        let experiment: Experiment = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::from_str(&contents).map_err(|error| error.to_string()),
+            Some("json") => serde_json::from_str(&contents).map_err(|error| error.to_string()),
+            _ => Err(String::from("expected a .toml or .json file")),
+        }
+        .map_err(|error| format!("invalid experiment file {}: {}", path.display(), error))?;
+        experiment.validate()?;
+        Ok(experiment)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.sweeps.is_empty() {
+            return Err(String::from("experiment file defines no sweeps"));
+        }
+        for (name, definition) in &self.sweeps {
+            definition
+                .validate()
+                .map_err(|error| format!("sweep '{}': {}", name, error))?;
+        }
+        let mut outputs: Vec<&PathBuf> = self.sweeps.values().map(|s| &s.output).collect();
+        outputs.sort();
+        if outputs.windows(2).any(|pair| pair[0] == pair[1]) {
+            return Err(String::from("sweeps must not share an output directory"));
+        }
+        Ok(())
+    }
+
+    /// Returns the sweeps with the given names, or every sweep when `names` is empty.
+    pub fn select(&self, names: &[String]) -> Result<Vec<(&String, &SweepDefinition)>, String> {
+        if names.is_empty() {
+            return Ok(self.sweeps.iter().collect());
+        }
+        names
+            .iter()
+            .map(|name| {
+                self.sweeps
+                    .get_key_value(name)
+                    .ok_or_else(|| format!("no sweep named '{}'", name))
+            })
+            .collect()
+    }
+}
+
+impl SweepDefinition {
+    /// Checks the settings for values and combinations the simulations do not support.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.sizes.is_empty() {
+            return Err(String::from("at least one lattice size is required"));
+        }
+        if let Some(size) = self.sizes.iter().find(|size| **size < 2) {
+            return Err(format!("lattice size {} is smaller than 2", size));
+        }
+        if self.updater.needs_even_size() {
+            if let Some(size) = self.sizes.iter().find(|size| *size % 2 == 1) {
+                return Err(format!(
+                    "the {} updater needs even lattice sizes, got {}",
+                    self.updater, size
+                ));
+            }
+        }
+        if self.later_steps == 0 {
+            return Err(String::from("later_steps must be positive"));
+        }
+        if self.magn_calc_step > self.later_steps {
+            return Err(format!(
+                "magn_calc_step ({}) must not exceed later_steps ({})",
+                self.magn_calc_step, self.later_steps
+            ));
+        }
+        if self.swap_interval == Some(0) {
+            return Err(String::from("swap_interval must be positive"));
+        }
+        if let Some(target_error) = self.target_error {
+            if !(target_error.is_finite() && target_error > 0.0) {
+                return Err(String::from("target_error must be positive"));
+            }
+            if self.swap_interval.is_some() {
+                return Err(String::from(
+                    "parallel tempering does not support target_error",
+                ));
+            }
+        }
+        if self.refine_rounds > 0 && self.refine_points == 0 {
+            return Err(String::from("refine_points must be positive"));
+        }
+        if self.swap_interval.is_some() && self.save_series {
+            return Err(String::from(
+                "parallel tempering does not support save_series",
+            ));
+        }
+        if self.swap_interval.is_some() && self.refine_rounds > 0 {
+            return Err(String::from(
+                "parallel tempering does not support refine_rounds",
+            ));
+        }
+        if self.swap_interval.is_some() && self.auto_equilibration {
+            return Err(String::from(
+                "parallel tempering does not support auto_equilibration",
+            ));
+        }
+        if self.auto_equilibration && self.initial_steps < FIRST_CHECK {
+            return Err(format!(
+                "auto_equilibration needs initial_steps of at least {}",
+                FIRST_CHECK
+            ));
+        }
+        if self.swap_interval.is_some() && !self.updater.is_single_lattice() {
+            return Err(format!(
+                "parallel tempering does not support the {} updater",
+                self.updater
+            ));
+        }
+        if self.magn_calc_step == 0 {
+            return Err(String::from("magn_calc_step must be positive"));
+        }
+        if let Some(magnetization) = self.initial_magnetization {
+            if !(-1.0..=1.0).contains(&magnetization) {
+                return Err(String::from("initial_magnetization must lie in [-1, 1]"));
+            }
+        }
+        self.temperatures.validate()?;
+        if self.temperatures.values().is_empty() {
+            return Err(String::from("temperature grid is empty"));
+        }
+        Ok(())
+    }
+
+    pub fn sweep(&self) -> Sweep {
+        Sweep {
+            sizes: self.sizes.clone(),
+            temperatures: self.temperatures.values(),
+            initial_steps: self.initial_steps,
+            auto_equilibration: self.auto_equilibration,
+            later_steps: self.later_steps,
+            magn_calc_step: self.magn_calc_step,
+            target_error: self.target_error,
+            target_observable: self.target_observable,
+            seed: self.seed.unwrap_or_else(rand::random),
+            rng: self.rng,
+            updater: self.updater,
+            initial_magnetization: self.initial_magnetization,
+            swap_interval: self.swap_interval,
+            error_method: self.error_method,
+            refine_rounds: self.refine_rounds,
+            refine_points: self.refine_points,
+            refine_observable: self.refine_observable,
+            save_series: self.save_series,
+        }
+    }
+}
+
+impl TemperatureGrid {
+    fn validate(&self) -> Result<(), String> {
+        let is_positive = |t: &f64| t.is_finite() && *t > 0.0;
+        match self {
+            TemperatureGrid::Linear { min, max, step } => {
+                if !is_positive(min) || !is_positive(max) || min >= max {
+                    return Err(String::from("linear grid needs 0 < min < max"));
+                }
+                if !is_positive(step) {
+                    return Err(String::from("linear grid step must be positive"));
+                }
+            }
+            TemperatureGrid::Log { min, max, count } => {
+                if !is_positive(min) || !is_positive(max) || min >= max {
+                    return Err(String::from("log grid needs 0 < min < max"));
+                }
+                if *count < 2 {
+                    return Err(String::from("log grid needs at least 2 points"));
+                }
+            }
+            TemperatureGrid::List(temperatures) => {
+                if temperatures.is_empty() {
+                    return Err(String::from("temperature list is empty"));
+                }
+                if !temperatures.iter().all(is_positive) {
+                    return Err(String::from("temperatures must be positive"));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn values(&self) -> Vec<f64> {
+        match self {
+            TemperatureGrid::Linear { min, max, step } => get_float_range(*min, *max, *step),
+            TemperatureGrid::Log { min, max, count } => get_log_range(*min, *max, *count),
+            TemperatureGrid::List(temperatures) => temperatures.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn float_range_starts_at_start_and_excludes_end() {
+        let range = get_float_range(0.001, 0.05, 0.01);
+        assert_eq!(range.len(), 5);
+        assert_eq!(range[0], 0.001);
+        assert_eq!(get_float_range(1.0, 2.0, 0.1).len(), 10);
+        assert_eq!(get_float_range(1.0, 5.0, 0.05).len(), 80);
+    }
+}
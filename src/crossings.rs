@@ -0,0 +1,210 @@
+use std::collections::BTreeSet;
+
+use crate::fitting::{fit_line, get_weights};
+use crate::results::Record;
+
+/// Temperature where the Binder cumulants of two lattice sizes cross.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Crossing {
+    pub smaller_size: usize,
+    pub larger_size: usize,
+    pub temperature: f64,
+    /// Standard error of `temperature`, propagated from the fit.
+    pub temperature_error: f64,
+    /// Binder cumulant at the crossing.
+    pub binder_cumulant: f64,
+}
+
+/// Finds the crossings of the Binder cumulant curves of consecutive lattice sizes.
+///
+/// A crossing is located wherever the difference of the two curves changes sign between
+/// neighbouring temperatures they share. The difference is fitted by a straight line through
+/// the `window` shared temperatures closest to the sign change, weighted by the errors of
+/// the cumulants, and the crossing temperature and its error follow from the root of that
+/// line. Only temperatures within `range` are considered.
+///
+/// The default window of two temperatures interpolates between the points bracketing the
+/// crossing, which avoids the bias that the curvature of the cumulants gives wider windows.
+/// The interpolation is solved directly, so it does not need the errors to be known.
+///
+/// Above Tc the cumulants of all sizes approach zero and noise produces spurious crossings,
+/// so `range` should bracket the critical region.
+pub fn find_crossings(records: &[Record], window: usize, range: (f64, f64)) -> Vec<Crossing> {
+    let sizes: BTreeSet<usize> = records.iter().map(|r| r.lattice_size).collect();
+    let sizes: Vec<usize> = sizes.into_iter().collect();
+    sizes
+        .windows(2)
+        .flat_map(|pair| find_pair_crossings(records, pair[0], pair[1], window, range))
+        .collect()
+}
+
+fn find_pair_crossings(
+    records: &[Record],
+    smaller_size: usize,
+    larger_size: usize,
+    window: usize,
+    range: (f64, f64),
+) -> Vec<Crossing> {
+    let curve = |size: usize| -> Vec<&Record> {
+        let mut curve: Vec<&Record> = records
+            .iter()
+            .filter(|r| r.lattice_size == size)
+            .filter(|r| (range.0..=range.1).contains(&r.temperature))
+            .collect();
+        curve.sort_by(|a, b| a.temperature.total_cmp(&b.temperature));
+        curve
+    };
+    let larger = curve(larger_size);
+    // (temperature, smaller cumulant, its error, difference, its error) at the shared temperatures
+    let points: Vec<(f64, f64, f64, f64, f64)> = curve(smaller_size)
+        .into_iter()
+        .filter_map(|small| {
+            larger
+                .iter()
+                .find(|large| large.temperature == small.temperature)
+                .map(|large| {
+                    (
+                        small.temperature,
+                        small.binder_cumulant,
+                        small.binder_cumulant_error,
+                        large.binder_cumulant - small.binder_cumulant,
+                        small
+                            .binder_cumulant_error
+                            .hypot(large.binder_cumulant_error),
+                    )
+                })
+        })
+        .collect();
+    if points.len() < window {
+        return Vec::new();
+    }
+
+    (0..points.len() - 1)
+        .filter(|i| points[*i].3.signum() != points[*i + 1].3.signum())
+        .filter_map(|i| {
+            // the window is centred on the sign change and clipped to the available points
+            let start = (i + 1)
+                .saturating_sub(window / 2)
+                .min(points.len() - window);
+            let window = &points[start..start + window];
+            if let [(t0, c0, _, d0, e0), (t1, c1, _, d1, e1)] = *window {
+                return get_interpolated_crossing(
+                    smaller_size,
+                    larger_size,
+                    [t0, t1],
+                    [c0, c1],
+                    [d0, d1],
+                    [e0, e1],
+                );
+            }
+            let temperatures: Vec<f64> = window.iter().map(|p| p.0).collect();
+            let cumulants: Vec<f64> = window.iter().map(|p| p.1).collect();
+            let cumulant_errors: Vec<f64> = window.iter().map(|p| p.2).collect();
+            let differences: Vec<f64> = window.iter().map(|p| p.3).collect();
+            let difference_errors: Vec<f64> = window.iter().map(|p| p.4).collect();
+
+            let difference_fit =
+                fit_line(&temperatures, &differences, get_weights(&difference_errors))?;
+            let (temperature, temperature_error) = difference_fit.root();
+            let cumulant_fit = fit_line(&temperatures, &cumulants, get_weights(&cumulant_errors))?;
+            Some(Crossing {
+                smaller_size,
+                larger_size,
+                temperature,
+                temperature_error,
+                binder_cumulant: cumulant_fit.value(temperature),
+            })
+        })
+        .collect()
+}
+
+/// Crossing found by linear interpolation between two temperatures, given the cumulants of
+/// the smaller size and the differences of the cumulants with their errors.
+fn get_interpolated_crossing(
+    smaller_size: usize,
+    larger_size: usize,
+    [t0, t1]: [f64; 2],
+    [c0, c1]: [f64; 2],
+    [d0, d1]: [f64; 2],
+    [e0, e1]: [f64; 2],
+) -> Option<Crossing> {
+    if d0 == d1 {
+        return None;
+    }
+    let temperature = (t0 * d1 - t1 * d0) / (d1 - d0);
+    let temperature_error = (t1 - t0) * (d1 * e0).hypot(d0 * e1) / (d1 - d0).powi(2);
+    let fraction = (temperature - t0) / (t1 - t0);
+    Some(Crossing {
+        smaller_size,
+        larger_size,
+        temperature,
+        temperature_error,
+        binder_cumulant: c0 + fraction * (c1 - c0),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_record(lattice_size: usize, temperature: f64, binder_cumulant: f64) -> Record {
+        Record {
+            lattice_size,
+            temperature,
+            magnetization: 0.0,
+            susceptibility: 0.0,
+            binder_cumulant,
+            energy: 0.0,
+            specific_heat: 0.0,
+            magnetization_squared: 0.0,
+            magnetization_fourth: 0.0,
+            energy_squared: 0.0,
+            magnetization_error: 0.0,
+            susceptibility_error: 0.0,
+            binder_cumulant_error: 0.0,
+            energy_error: 0.0,
+            specific_heat_error: 0.0,
+            magnetization_autocorrelation_time: 0.0,
+            energy_autocorrelation_time: 0.0,
+            effective_samples: 0.0,
+            thermalization: 0,
+            measurement_steps: 0,
+        }
+    }
+
+    #[test]
+    fn interpolates_crossings_without_errors() {
+        let records = [
+            get_record(8, 2.2, 0.60),
+            get_record(8, 2.3, 0.50),
+            get_record(16, 2.2, 0.64),
+            get_record(16, 2.3, 0.42),
+        ];
+        let crossings = find_crossings(&records, 2, (2.0, 2.5));
+        assert_eq!(crossings.len(), 1);
+        assert!((crossings[0].temperature - (2.2 + 0.1 / 3.0)).abs() < 1e-12);
+        assert!((crossings[0].binder_cumulant - (0.6 - 0.1 / 3.0)).abs() < 1e-12);
+        assert_eq!(crossings[0].temperature_error, 0.0);
+    }
+
+    #[test]
+    fn interpolation_matches_weighted_fit() {
+        let errors = [0.01, 0.02, 0.015, 0.005];
+        let mut records = [
+            get_record(8, 2.2, 0.60),
+            get_record(8, 2.3, 0.50),
+            get_record(16, 2.2, 0.64),
+            get_record(16, 2.3, 0.42),
+        ];
+        for (record, error) in records.iter_mut().zip(errors) {
+            record.binder_cumulant_error = error;
+        }
+        let crossing = &find_crossings(&records, 2, (2.0, 2.5))[0];
+        let differences = [0.04, -0.08];
+        let difference_errors = [0.01f64.hypot(0.015), 0.02f64.hypot(0.005)];
+        let fit = fit_line(&[2.2, 2.3], &differences, Some(&difference_errors)).unwrap();
+        let (temperature, temperature_error) = fit.root();
+        assert!((crossing.temperature - temperature).abs() < 1e-9);
+        assert!((crossing.temperature_error - temperature_error).abs() < 1e-9);
+    }
+}
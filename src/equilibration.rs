@@ -0,0 +1,43 @@
+use crate::statistics::{get_binning_error, get_mean};
+
+/// Sweeps after which the first drift test is made, and so the fewest sweeps automatic
+/// equilibration may be given.
+pub const FIRST_CHECK: u32 = 256;
+/// Largest difference between the mean energies of consecutive windows, in standard errors,
+/// that is not considered a drift.
+const DRIFT_THRESHOLD: f64 = 2.0;
+
+/// Thermalizes a chain until its energy stops drifting, performing at most `max_steps` sweeps.
+///
+/// `sweep` advances the chain by one sweep and returns its energy. After `256, 512, 1024, ...`
+/// sweeps the mean energies of the last two quarters of the sweeps done so far are compared,
+/// and thermalization ends as soon as they agree within their errors. Discarding the first
+/// half of the sweeps at every check keeps the initial relaxation out of the test.
+///
+/// The test cannot tell metastable states whose energy does not drift, such as the stripes
+/// a random lattice may freeze into at low temperatures, from equilibrium; starting from an
+/// ordered lattice avoids them below Tc.
+///
+/// Returns the number of sweeps performed.
+pub fn equilibrate<F: FnMut() -> f64>(max_steps: u32, mut sweep: F) -> u32 {
+    let mut energies = Vec::new();
+    let mut check = FIRST_CHECK as usize;
+    while energies.len() < max_steps as usize {
+        energies.push(sweep());
+        if energies.len() == check {
+            if !is_drifting(&energies[check / 2..]) {
+                break;
+            }
+            check *= 2;
+        }
+    }
+    energies.len() as u32
+}
+
+/// Whether the means of the two halves of the series differ by more than their errors allow.
+pub fn is_drifting(series: &[f64]) -> bool {
+    let (older, newer) = series.split_at(series.len() / 2);
+    let difference = (get_mean(newer) - get_mean(older)).abs();
+    let error = get_binning_error(older).hypot(get_binning_error(newer));
+    difference > DRIFT_THRESHOLD * error
+}
@@ -0,0 +1,85 @@
+/// Straight line `intercept + slope * x` fitted by least squares.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LineFit {
+    pub intercept: f64,
+    pub slope: f64,
+    /// Covariance matrix of `(intercept, slope)`.
+    pub covariance: [[f64; 2]; 2],
+    pub chi_squared: f64,
+    pub degrees_of_freedom: usize,
+}
+
+impl LineFit {
+    pub fn value(&self, x: f64) -> f64 {
+        self.intercept + self.slope * x
+    }
+
+    /// Position where the line crosses zero, with its standard error.
+    pub fn root(&self) -> (f64, f64) {
+        let root = -self.intercept / self.slope;
+        let [[var_intercept, covariance], [_, var_slope]] = self.covariance;
+        let variance = (var_intercept + 2.0 * root * covariance + root * root * var_slope)
+            / (self.slope * self.slope);
+        (root, variance.sqrt())
+    }
+}
+
+/// Fits a straight line through the points, weighting them by their standard errors.
+///
+/// Without errors all points are weighted equally and the covariance is scaled by the
+/// residual variance, `chi_squared / degrees_of_freedom`, which requires at least three
+/// points. Returns `None` when the fit is undetermined.
+pub fn fit_line(x: &[f64], y: &[f64], errors: Option<&[f64]>) -> Option<LineFit> {
+    let weights: Vec<f64> = match errors {
+        Some(errors) => errors.iter().map(|error| 1.0 / (error * error)).collect(),
+        None => vec![1.0; x.len()],
+    };
+    let (mut s, mut sx, mut sy, mut sxx, mut sxy) = (0.0, 0.0, 0.0, 0.0, 0.0);
+    for ((x, y), w) in x.iter().zip(y).zip(&weights) {
+        s += w;
+        sx += w * x;
+        sy += w * y;
+        sxx += w * x * x;
+        sxy += w * x * y;
+    }
+    let determinant = s * sxx - sx * sx;
+    if !(determinant.is_finite() && determinant > 0.0) {
+        return None;
+    }
+    let intercept = (sxx * sy - sx * sxy) / determinant;
+    let slope = (s * sxy - sx * sy) / determinant;
+    let chi_squared: f64 = x
+        .iter()
+        .zip(y)
+        .zip(&weights)
+        .map(|((x, y), w)| w * (y - intercept - slope * x).powi(2))
+        .sum();
+    let degrees_of_freedom = x.len() - 2;
+
+    let scale = match errors {
+        Some(_) => 1.0,
+        None if degrees_of_freedom > 0 => chi_squared / degrees_of_freedom as f64,
+        None => return None,
+    };
+    let covariance = [
+        [scale * sxx / determinant, -scale * sx / determinant],
+        [-scale * sx / determinant, scale * s / determinant],
+    ];
+    Some(LineFit {
+        intercept,
+        slope,
+        covariance,
+        chi_squared,
+        degrees_of_freedom,
+    })
+}
+
+/// Errors to weight a fit with, or `None` to weight the points equally when some are
+/// missing, e.g. because a cumulant did not fluctuate.
+pub fn get_weights(errors: &[f64]) -> Option<&[f64]> {
+    if errors.iter().all(|error| error.is_finite() && *error > 0.0) {
+        Some(errors)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+
+use rand::Rng;
+
+use crate::lattice::{get_neighbour_table, Lattice};
+use crate::metropolis::{get_acceptance_array, get_acceptance_index, get_acceptance_thresholds};
+use crate::updater::Update;
+
+/// Heat-bath (Glauber) acceptance probabilities keyed by the energy change of a single spin flip.
+pub fn get_glauber_map(temp: f64) -> HashMap<i8, f64> {
+    [-8, -4, 0, 4, 8]
+        .iter()
+        .map(|energy_change| {
+            (
+                *energy_change,
+                1.0 / (1.0 + (*energy_change as f64 / temp).exp()),
+            )
+        })
+        .collect()
+}
+
+/// Performs one random-sequential sweep: as many heat-bath updates as there are sites, each
+/// at a site chosen at random. Returns the change of the total energy over the sweep.
+pub fn flip_random_spins<R: Rng + ?Sized>(
+    lattice: &mut Lattice,
+    neighbours: &[[usize; 4]],
+    thresholds: &[u64; 5],
+    rng: &mut R,
+) -> i64 {
+    let mut total_change = 0;
+    let spins = lattice.spins_mut();
+    for _ in 0..spins.len() {
+        let index = rng.gen_range(0..spins.len());
+        let spin = spins[index];
+        let neighbour_sum: i8 = neighbours[index].iter().map(|i| spins[*i]).sum();
+        let threshold = thresholds[get_acceptance_index(spin, neighbour_sum)];
+        if threshold == u64::MAX || rng.next_u64() < threshold {
+            spins[index] = -spin;
+            total_change += 2 * (spin * neighbour_sum) as i64;
+        }
+    }
+    total_change
+}
+
+/// Single-spin heat-bath updates at random sites, i.e. Glauber kinetics.
+pub struct Glauber {
+    neighbours: Vec<[usize; 4]>,
+    thresholds: [u64; 5],
+}
+
+impl Glauber {
+    pub fn new(temperature: f64, lattice_size: usize) -> Self {
+        Glauber {
+            neighbours: get_neighbour_table(lattice_size),
+            thresholds: get_acceptance_thresholds(&get_acceptance_array(&get_glauber_map(
+                temperature,
+            ))),
+        }
+    }
+}
+
+impl Update for Glauber {
+    fn sweep<R: Rng + ?Sized>(&mut self, lattice: &mut Lattice, rng: &mut R) -> i64 {
+        flip_random_spins(lattice, &self.neighbours, &self.thresholds, rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sweep::tests::assert_agrees_with_metropolis;
+    use crate::updater::Updater;
+
+    #[test]
+    fn agrees_with_metropolis() {
+        assert_agrees_with_metropolis(Updater::Glauber);
+    }
+}
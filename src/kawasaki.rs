@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+
+use rand::Rng;
+
+use crate::lattice::Lattice;
+use crate::updater::Update;
+
+/// Metropolis acceptance probabilities keyed by the energy change of exchanging
+/// two neighbouring, opposite spins.
+pub fn get_kawasaki_map(temp: f64) -> HashMap<i8, f64> {
+    (-3..=3)
+        .map(|step| {
+            let energy_change = 4 * step;
+            (energy_change, (-energy_change as f64 / temp).exp().min(1.0))
+        })
+        .collect()
+}
+
+/// Performs one Kawasaki sweep: as many exchange attempts as there are sites, each
+/// between a random site and one of its neighbours, chosen at random.
+/// Returns the change of the total energy over the sweep.
+pub fn exchange_spins<R: Rng + ?Sized>(
+    lattice: &mut Lattice,
+    trans_map: &HashMap<i8, f64>,
+    rng: &mut R,
+) -> i64 {
+    let mut total_change = 0;
+    for _ in 0..lattice.len() {
+        let index = rng.gen_range(0..lattice.len());
+        let neighbour = lattice.adjacent(index)[rng.gen_range(0..4)];
+        let spin = lattice.spins()[index];
+        let neighbour_spin = lattice.spins()[neighbour];
+        if spin == neighbour_spin {
+            continue;
+        }
+        // the bonds between the pair are unchanged by the exchange, hence the `+ 4` for each;
+        // on a lattice of size 2 the pair shares two bonds
+        let shared_bonds = lattice
+            .adjacent(index)
+            .iter()
+            .filter(|adjacent| **adjacent == neighbour)
+            .count() as i8;
+        let energy_change = 2
+            * (spin * lattice.neighbour_sum(index)
+                + neighbour_spin * lattice.neighbour_sum(neighbour))
+            + 4 * shared_bonds;
+        if rng.gen_bool(trans_map[&energy_change]) {
+            lattice.spins_mut()[index] = neighbour_spin;
+            lattice.spins_mut()[neighbour] = spin;
+            total_change += energy_change as i64;
+        }
+    }
+    total_change
+}
+
+/// Kawasaki spin-exchange dynamics, conserving the magnetization.
+pub struct Kawasaki {
+    trans_map: HashMap<i8, f64>,
+}
+
+impl Kawasaki {
+    pub fn new(temperature: f64) -> Self {
+        Kawasaki {
+            trans_map: get_kawasaki_map(temperature),
+        }
+    }
+}
+
+impl Update for Kawasaki {
+    fn sweep<R: Rng + ?Sized>(&mut self, lattice: &mut Lattice, rng: &mut R) -> i64 {
+        exchange_spins(lattice, &self.trans_map, rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::observables::get_energy;
+    use crate::rng::Xoshiro256PlusPlus;
+
+    #[test]
+    fn energy_changes_add_up_on_small_lattices() {
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(13);
+        for size in [2, 3, 4] {
+            let mut lattice = Lattice::with_magnetization(size, 0.0, &mut rng);
+            let mut energy = get_energy(&lattice);
+            let mut updater = Kawasaki::new(2.0);
+            for _ in 0..1_000 {
+                energy += updater.sweep(&mut lattice, &mut rng);
+            }
+            assert_eq!(energy, get_energy(&lattice), "L = {}", size);
+        }
+    }
+}
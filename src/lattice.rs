@@ -0,0 +1,136 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+
+/// Square lattice of `size * size` spins with periodic boundary conditions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lattice {
+    size: usize,
+    spins: Vec<i8>,
+}
+
+impl Lattice {
+    /// Returns a lattice with every spin drawn uniformly from {-1, 1}.
+    pub fn random<R: Rng + ?Sized>(size: usize, rng: &mut R) -> Self {
+        Lattice {
+            size,
+            spins: generate_lattice(size, rng),
+        }
+    }
+
+    /// Returns a random lattice whose magnetization per spin is as close to `magnetization` as
+    /// the lattice size allows.
+    pub fn with_magnetization<R: Rng + ?Sized>(
+        size: usize,
+        magnetization: f64,
+        rng: &mut R,
+    ) -> Self {
+        Lattice {
+            size,
+            spins: generate_lattice_with_magnetization(size, magnetization, rng),
+        }
+    }
+
+    /// Wraps already generated spins, stored row by row.
+    pub fn from_spins(size: usize, spins: Vec<i8>) -> Self {
+        assert_eq!(spins.len(), size * size, "expected {} spins", size * size);
+        Lattice { size, spins }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Number of spins in the lattice.
+    pub fn len(&self) -> usize {
+        self.spins.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spins.is_empty()
+    }
+
+    pub fn spins(&self) -> &[i8] {
+        &self.spins
+    }
+
+    pub fn spins_mut(&mut self) -> &mut [i8] {
+        &mut self.spins
+    }
+
+    pub fn adjacent(&self, index: usize) -> [usize; 4] {
+        get_adjacent_indices(index, self.size)
+    }
+
+    /// Sum of the four neighbouring spins of the site at `index`.
+    pub fn neighbour_sum(&self, index: usize) -> i8 {
+        self.adjacent(index).iter().map(|i| self.spins[*i]).sum()
+    }
+
+    /// Sum of all spins.
+    pub fn total_spin(&self) -> i64 {
+        self.spins.par_iter().map(|e| *e as i64).sum()
+    }
+}
+
+pub fn generate_lattice<R: Rng + ?Sized>(size: usize, rng: &mut R) -> Vec<i8> {
+    (0..size * size)
+        .map(|_| if rng.gen() { 1 } else { -1 })
+        .collect()
+}
+
+/// Generates a lattice with a fixed number of up spins, placed uniformly at random.
+pub fn generate_lattice_with_magnetization<R: Rng + ?Sized>(
+    size: usize,
+    magnetization: f64,
+    rng: &mut R,
+) -> Vec<i8> {
+    let sites = size * size;
+    let up = ((1.0 + magnetization) / 2.0 * sites as f64).round() as usize;
+    let mut spins: Vec<i8> = (0..sites).map(|i| if i < up { 1 } else { -1 }).collect();
+    spins.shuffle(rng);
+    spins
+}
+
+/// Adjacent indices of every site, in the order of [`get_adjacent_indices`],
+/// so that hot loops can look neighbours up instead of recomputing them.
+pub fn get_neighbour_table(size: usize) -> Vec<[usize; 4]> {
+    (0..size * size)
+        .map(|index| get_adjacent_indices(index, size))
+        .collect()
+}
+
+pub fn get_adjacent_indices(index: usize, size: usize) -> [usize; 4] {
+    /*
+    Returns adjacent indices on a torus,
+    represented in a vector, in the following form:
+    [left, top, right, bottom]
+    */
+    let bottom_left = size * size - size;
+    [
+        if index.is_multiple_of(size) {
+            // left side
+            index + size - 1
+        } else {
+            index - 1
+        },
+        if index < size {
+            // top side
+            index + bottom_left
+        } else {
+            index - size
+        },
+        if index % size == size - 1 {
+            // right side
+            index + 1 - size
+        } else {
+            index + 1
+        },
+        if index >= bottom_left {
+            // bottom side
+            index - bottom_left
+        } else {
+            index + size
+        },
+    ]
+}
@@ -0,0 +1,38 @@
+//! Monte Carlo simulations of the two-dimensional Ising model.
+//!
+//! - [`lattice`] and [`observables`]: spin configurations and what is measured on them.
+//! - [`updater`]: the update rules, implemented in [`metropolis`], [`glauber`],
+//!   [`checkerboard`], [`wolff`], [`swendsen_wang`] and [`kawasaki`].
+//! - [`multispin`]: 64 replicas packed into machine words for fast Metropolis sweeps.
+//! - [`sweep`]: sweeps of independent simulations over lattice sizes and temperatures.
+//! - [`tempering`]: the same sweeps run as replicas exchanging configurations.
+//! - [`wang_landau`] and [`multicanonical`]: density of states and flat-histogram sampling.
+//! - [`coarsening`]: domain growth after a quench.
+//! - [`crossings`], [`scaling`] and [`reweighting`]: analysis of finished sweeps.
+
+pub mod benchmark;
+pub mod checkerboard;
+pub mod coarsening;
+pub mod config;
+pub mod crossings;
+pub mod equilibration;
+pub mod fitting;
+pub mod glauber;
+pub mod kawasaki;
+pub mod lattice;
+pub mod metropolis;
+pub mod multicanonical;
+pub mod multispin;
+pub mod observables;
+pub mod refinement;
+pub mod results;
+pub mod reweighting;
+pub mod rng;
+pub mod scaling;
+pub mod statistics;
+pub mod sweep;
+pub mod swendsen_wang;
+pub mod tempering;
+pub mod updater;
+pub mod wang_landau;
+pub mod wolff;
@@ -1,181 +1,964 @@
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::Write;
-use std::sync::{Arc, Mutex};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 use std::time::Instant;
 
+use clap::{error::ErrorKind, Args, CommandFactory, Parser, Subcommand};
 use console::Emoji;
+use ising::benchmark::Benchmark;
+use ising::coarsening::Coarsening;
+use ising::config::{self, Experiment, Sweep, SweepDefinition, TargetObservable, TemperatureGrid};
+use ising::crossings::find_crossings;
+use ising::multicanonical::{get_interface_tension, Multicanonical, Variable};
+use ising::observables::TimeSeries;
+use ising::refinement::{run_refined_sweep, RefinementObservable};
+use ising::results::{
+    read_results, read_time_series, write_coarsening, write_collapse, write_collapse_quality,
+    write_crossings, write_density_of_states, write_log_distribution, write_multi_histograms,
+    write_multicanonical_weights, write_results, write_reweighted, write_scaling_fits,
+    write_single_histograms, write_structure_factor, write_susceptibility_peaks, write_swap_rates,
+    write_thermodynamics, write_time_series, Record,
+};
+use ising::reweighting::{Measurements, MAX_ITERATIONS};
+use ising::rng::RngKind;
+use ising::statistics::ErrorMethod;
+use ising::sweep::run_sweep;
+use ising::tempering::run_tempering_sweep;
+use ising::updater::Updater;
+use ising::wang_landau::WangLandau;
 use linya::{Bar, Progress};
-use rand::Rng;
-use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
-
-const MIN_TEMP: f64 = 1.0;
-const MAX_TEMP: f64 = 5.0;
-const TEMP_STEP: f64 = 0.05;
-const INITIAL_STEPS: u32 = 30_000;
-const LATER_STEPS: u32 = 200_000;
-const MAGN_CALC_STEP: u32 = 100;
-const MAGN_STEPS: u32 = (LATER_STEPS / MAGN_CALC_STEP) as u32;
-const LATTICE_SIZES: [usize; 4] = [6, 15, 40, 70];
 
 static SPARKLE: Emoji<'_, '_> = Emoji("✨", ":)");
 static ROCKET: Emoji<'_, '_> = Emoji("🚀", ":o");
 
-fn generate_lattice(size: usize) -> Vec<i8> {
-    (0..size * size)
-        .map(|_| if rand::random() { 1 } else { -1 })
-        .collect()
-}
-
-fn get_adjacent_indices(index: usize, size: usize) -> [usize; 4] {
-    /*
-    Returns adjacent indices on a torus,
-    represented in a vector, in the following form:
-    [left, top, right, bottom]
-    */
-    let bottom_left = size * size - size;
-    [
-        if index % size == 0 {
-            // left side
-            index + size - 1
-        } else {
-            index - 1
-        },
-        if index < size {
-            // top side
-            index + bottom_left
-        } else {
-            index - size
-        },
-        if index % size == size - 1 {
-            // right side
-            index + 1 - size
-        } else {
-            index + 1
-        },
-        if index >= bottom_left {
-            // bottom side
-            index - bottom_left
-        } else {
-            index + size
-        },
-    ]
-}
-
-fn get_trans_map(temp: f64) -> HashMap<i8, f64> {
-    [
-        (-8, (8.0 / temp).exp().min(1.0)),
-        (-4, (4.0 / temp).exp().min(1.0)),
-        (0, 1.0),
-        (4, (-4.0 / temp).exp().min(1.0)),
-        (8, (-8.0 / temp).exp().min(1.0)),
-    ]
-    .iter()
-    .cloned()
-    .collect()
-}
-
-fn recalc_lattice(lattice: &mut Vec<i8>, size: usize, trans_map: &HashMap<i8, f64>) {
-    for index in 0..size * size {
-        let spin = lattice[index];
-        let energy_change = 2
-            * spin
-            * get_adjacent_indices(index, size)
-                .iter()
-                .map(|i| lattice[*i])
-                .sum::<i8>();
-        let mut rng = rand::thread_rng();
-        if rng.gen_bool(trans_map[&energy_change]) {
-            lattice[index] = -spin;
+/// Monte Carlo simulations of the 2D Ising model.
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run a sweep over lattice sizes and temperatures
+    Run(RunArgs),
+    /// Run named sweeps defined in a TOML or JSON experiment file
+    Experiment(ExperimentArgs),
+    /// Follow domain growth under Kawasaki dynamics after a quench
+    Coarsen(CoarsenArgs),
+    /// Estimate the density of states with the Wang–Landau algorithm
+    WangLandau(WangLandauArgs),
+    /// Flatten the energy or magnetization histogram with multicanonical weights
+    Multicanonical(MulticanonicalArgs),
+    /// Locate Tc from crossings of the Binder cumulants of consecutive lattice sizes
+    Crossings(CrossingsArgs),
+    /// Estimate Tc and the critical exponents by finite-size scaling and collapse the results
+    Scaling(ScalingArgs),
+    /// Reweight saved time series to smooth curves and locate the susceptibility peaks
+    Reweight(ReweightArgs),
+    /// Time the ways single-spin Metropolis sweeps can decide a flip
+    Bench(BenchArgs),
+}
+
+#[derive(Args)]
+struct RunArgs {
+    /// Lowest temperature of the sweep
+    #[arg(long, default_value_t = 1.0, value_parser = parse_positive_f64)]
+    min_temp: f64,
+
+    /// Upper bound (exclusive) of the temperature sweep
+    #[arg(long, default_value_t = 5.0, value_parser = parse_positive_f64)]
+    max_temp: f64,
+
+    /// Distance between consecutive temperatures
+    #[arg(long, default_value_t = 0.05, value_parser = parse_positive_f64)]
+    temp_step: f64,
+
+    /// Lattice sweeps discarded before measuring
+    #[arg(long, default_value_t = config::DEFAULT_INITIAL_STEPS)]
+    initial_steps: u32,
+
+    /// Stop thermalizing as soon as the energy stops drifting, discarding at most
+    /// --initial-steps sweeps, which must be at least 256
+    #[arg(long)]
+    auto_equilibration: bool,
+
+    /// Lattice sweeps performed while measuring
+    #[arg(
+        long,
+        default_value_t = config::DEFAULT_LATER_STEPS,
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    later_steps: u32,
+
+    /// Number of sweeps between magnetization measurements
+    #[arg(
+        long,
+        default_value_t = config::DEFAULT_MAGN_CALC_STEP,
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    magn_calc_step: u32,
+
+    /// Stop measuring once the relative error of --target-observable drops to this value,
+    /// performing at most --later-steps sweeps
+    #[arg(long, value_parser = parse_positive_f64)]
+    target_error: Option<f64>,
+
+    /// Observable whose relative error --target-error applies to
+    #[arg(long, value_enum, default_value_t)]
+    target_observable: TargetObservable,
+
+    /// Comma-separated lattice sizes
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_values_t = config::DEFAULT_LATTICE_SIZES,
+        value_parser = parse_lattice_size
+    )]
+    sizes: Vec<usize>,
+
+    /// Master seed for reproducible runs; a random one is drawn when omitted
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Random number generator used by the simulations
+    #[arg(long, value_enum, default_value_t)]
+    rng: RngKind,
+
+    /// Monte Carlo update algorithm
+    #[arg(long, value_enum, default_value_t)]
+    updater: Updater,
+
+    /// Start from a random lattice with this magnetization per spin
+    #[arg(long, allow_negative_numbers = true, value_parser = parse_magnetization)]
+    initial_magnetization: Option<f64>,
+
+    /// Couple the temperatures with parallel tempering, attempting replica swaps
+    /// every SWEEPS sweeps; swap rates are written next to the results
+    #[arg(long, value_name = "SWEEPS", value_parser = clap::value_parser!(u32).range(1..))]
+    swap_interval: Option<u32>,
+
+    /// Rounds of adding temperatures where --refine-observable peaks or changes fastest
+    #[arg(long, default_value_t = 0)]
+    refine_rounds: u32,
+
+    /// Temperatures added per lattice size and refinement round
+    #[arg(
+        long,
+        default_value_t = config::DEFAULT_REFINE_POINTS,
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    refine_points: u32,
+
+    /// Observable whose shape guides the refinement
+    #[arg(long, value_enum, default_value_t)]
+    refine_observable: RefinementObservable,
+
+    /// Resampling method for the errors of susceptibility, Binder cumulant and specific heat
+    #[arg(long, value_enum, default_value_t)]
+    error_method: ErrorMethod,
+
+    /// Save the energy and magnetization measurements of every simulation for `reweight`,
+    /// one file per simulation in a directory next to the results
+    #[arg(long)]
+    save_series: bool,
+
+    /// File the results are written to
+    #[arg(short, long, default_value = "ising.txt")]
+    output: PathBuf,
+}
+
+impl RunArgs {
+    /// The sweep as an experiment file would define it, with a linear temperature grid.
+    fn definition(&self) -> SweepDefinition {
+        SweepDefinition {
+            sizes: self.sizes.clone(),
+            temperatures: TemperatureGrid::Linear {
+                min: self.min_temp,
+                max: self.max_temp,
+                step: self.temp_step,
+            },
+            initial_steps: self.initial_steps,
+            auto_equilibration: self.auto_equilibration,
+            later_steps: self.later_steps,
+            magn_calc_step: self.magn_calc_step,
+            target_error: self.target_error,
+            target_observable: self.target_observable,
+            seed: self.seed,
+            rng: self.rng,
+            updater: self.updater,
+            initial_magnetization: self.initial_magnetization,
+            swap_interval: self.swap_interval,
+            error_method: self.error_method,
+            refine_rounds: self.refine_rounds,
+            refine_points: self.refine_points,
+            refine_observable: self.refine_observable,
+            save_series: self.save_series,
+            output: self.output.clone(),
         }
     }
 }
 
-fn get_magnetization(lattice: &Vec<i8>) -> f64 {
-    (lattice.par_iter().map(|e| *e as i32).sum::<i32>() as f64 / lattice.len() as f64).abs()
+#[derive(Args)]
+struct ExperimentArgs {
+    /// Experiment file (.toml or .json)
+    file: PathBuf,
+
+    /// Names of the sweeps to run; all sweeps are run when omitted
+    #[arg(short, long)]
+    sweep: Vec<String>,
 }
 
-fn get_float_range(start: f64, end: f64, step: f64) -> Vec<f64> {
-    ((start * 100.0) as i32..(end * 100.0) as i32)
-        .step_by((step * 100.0) as usize)
-        .map(|x| x as f64 * 0.01)
-        .collect()
+#[derive(Args)]
+struct CoarsenArgs {
+    /// Lattice size
+    #[arg(long, default_value_t = 128, value_parser = parse_lattice_size)]
+    size: usize,
+
+    /// Temperature the random initial lattice is quenched to
+    #[arg(long, default_value_t = 1.5, value_parser = parse_positive_f64)]
+    temperature: f64,
+
+    /// Conserved magnetization per spin
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true, value_parser = parse_magnetization)]
+    magnetization: f64,
+
+    /// Number of Kawasaki sweeps
+    #[arg(long, default_value_t = 10_000, value_parser = clap::value_parser!(u32).range(1..))]
+    steps: u32,
+
+    /// Number of logarithmically spaced measurements
+    #[arg(long, default_value_t = 40, value_parser = clap::value_parser!(u32).range(1..))]
+    measurements: u32,
+
+    /// Seed of the simulation; a random one is drawn when omitted
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Random number generator used by the simulation
+    #[arg(long, value_enum, default_value_t)]
+    rng: RngKind,
+
+    /// File the domain size time series is written to
+    #[arg(short, long, default_value = "coarsening.txt")]
+    output: PathBuf,
+
+    /// File the final structure factor is written to
+    #[arg(long, default_value = "structure_factor.txt")]
+    structure_factor: PathBuf,
 }
 
-fn get_params() -> Vec<(usize, f64, HashMap<i8, f64>)> {
-    let temperatures = get_float_range(MIN_TEMP, MAX_TEMP, TEMP_STEP);
-    LATTICE_SIZES
-        .iter()
-        .flat_map(|lattice_size| {
-            temperatures.iter().map(move |temp| {
-                let trans_map = get_trans_map(*temp);
-                (*lattice_size, *temp, trans_map)
-            })
-        })
-        .collect()
+#[derive(Args)]
+struct WangLandauArgs {
+    /// Lattice size
+    #[arg(long, default_value_t = 16, value_parser = parse_lattice_size)]
+    size: usize,
+
+    /// Minimal ratio of every histogram entry to the mean before ln f is halved
+    #[arg(long, default_value_t = 0.8, value_parser = parse_fraction)]
+    flatness: f64,
+
+    /// Stop once ln f, which starts at 1, drops below this value
+    #[arg(long, default_value_t = 1e-6, value_parser = parse_fraction)]
+    final_modification: f64,
+
+    /// Sweeps between histogram flatness checks
+    #[arg(long, default_value_t = 1000, value_parser = clap::value_parser!(u32).range(1..))]
+    check_interval: u32,
+
+    /// Lowest temperature of the derived thermodynamic curves
+    #[arg(long, default_value_t = 1.0, value_parser = parse_positive_f64)]
+    min_temp: f64,
+
+    /// Upper bound (exclusive) of the derived thermodynamic curves
+    #[arg(long, default_value_t = 5.0, value_parser = parse_positive_f64)]
+    max_temp: f64,
+
+    /// Distance between consecutive temperatures of the derived curves
+    #[arg(long, default_value_t = 0.01, value_parser = parse_positive_f64)]
+    temp_step: f64,
+
+    /// Seed of the simulation; a random one is drawn when omitted
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Random number generator used by the simulation
+    #[arg(long, value_enum, default_value_t)]
+    rng: RngKind,
+
+    /// File the log density of states is written to
+    #[arg(short, long, default_value = "dos.txt")]
+    output: PathBuf,
+
+    /// File the derived thermodynamic curves are written to
+    #[arg(long, default_value = "thermodynamics.txt")]
+    thermodynamics: PathBuf,
 }
 
-fn iteration(lattice_size: usize, temperature: f64, trans_map: &HashMap<i8, f64>) -> (f64, f64) {
-    let mut lattice = generate_lattice(lattice_size);
+#[derive(Args)]
+struct MulticanonicalArgs {
+    /// Lattice size
+    #[arg(long, default_value_t = 16, value_parser = parse_lattice_size)]
+    size: usize,
 
-    (0..INITIAL_STEPS).for_each(|_| {
-        recalc_lattice(&mut lattice, lattice_size, &trans_map);
-    });
+    /// Quantity whose histogram is flattened
+    #[arg(long, value_enum, default_value_t)]
+    variable: Variable,
 
-    let (magn_sum, magn_sqrt_sum) = (0..LATER_STEPS).into_iter().fold((0.0, 0.0), |acc, i| {
-        recalc_lattice(&mut lattice, lattice_size, &trans_map);
-        if i % MAGN_CALC_STEP == 0 {
-            let current_magnetization = get_magnetization(&lattice);
+    /// Simulation temperature; sets only the initial weights when flattening the energy
+    #[arg(long, default_value_t = 2.0, value_parser = parse_positive_f64)]
+    temperature: f64,
 
-            return (
-                acc.0 + current_magnetization,
-                acc.1 + current_magnetization * current_magnetization,
-            );
-        }
-        return acc;
-    });
+    /// Number of weight refinement runs
+    #[arg(long, default_value_t = 30)]
+    iterations: u32,
+
+    /// Sweeps per weight refinement run
+    #[arg(long, default_value_t = 20_000, value_parser = clap::value_parser!(u32).range(1..))]
+    iteration_sweeps: u32,
+
+    /// Sweeps of the production run
+    #[arg(long, default_value_t = 200_000, value_parser = clap::value_parser!(u32).range(1..))]
+    production_sweeps: u32,
+
+    /// Lowest temperature to reweight to
+    #[arg(long, default_value_t = 1.0, value_parser = parse_positive_f64)]
+    min_temp: f64,
+
+    /// Upper bound (exclusive) of the temperatures to reweight to
+    #[arg(long, default_value_t = 5.0, value_parser = parse_positive_f64)]
+    max_temp: f64,
+
+    /// Distance between consecutive temperatures to reweight to
+    #[arg(long, default_value_t = 0.01, value_parser = parse_positive_f64)]
+    temp_step: f64,
+
+    /// Seed of the simulation; a random one is drawn when omitted
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Random number generator used by the simulation
+    #[arg(long, value_enum, default_value_t)]
+    rng: RngKind,
+
+    /// File the reweighted canonical averages are written to
+    #[arg(short, long, default_value = "multicanonical.txt")]
+    output: PathBuf,
+
+    /// File the final weights and production histogram are written to
+    #[arg(long, default_value = "weights.txt")]
+    weights: PathBuf,
+
+    /// File the canonical distribution of the variable at --temperature is written to
+    #[arg(long, default_value = "distribution.txt")]
+    distribution: PathBuf,
+}
+
+#[derive(Args)]
+struct CrossingsArgs {
+    /// Results files written by `run` or `experiment`
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+
+    /// Number of temperatures around each crossing the curves are fitted over; more than two
+    /// average out noise but assume the curves are straight over the whole window
+    #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u32).range(2..))]
+    window: u32,
+
+    /// Ignore temperatures below this one
+    #[arg(long, default_value_t = 0.0)]
+    min_temp: f64,
+
+    /// Ignore temperatures above this one
+    #[arg(long, default_value_t = f64::INFINITY)]
+    max_temp: f64,
+
+    /// File the crossings are written to
+    #[arg(short, long, default_value = "crossings.txt")]
+    output: PathBuf,
+}
+
+#[derive(Args)]
+struct ScalingArgs {
+    /// Results files written by `run` or `experiment`, covering at least two lattice sizes
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+
+    /// Ignore temperatures below this one
+    #[arg(long, default_value_t = 0.0)]
+    min_temp: f64,
+
+    /// Ignore temperatures above this one
+    #[arg(long, default_value_t = f64::INFINITY)]
+    max_temp: f64,
+
+    /// Use this critical temperature instead of extrapolating the susceptibility peaks
+    #[arg(long, value_parser = parse_positive_f64)]
+    critical_temperature: Option<f64>,
+
+    /// Use this correlation length exponent instead of fitting the Binder cumulant slopes
+    #[arg(long, value_parser = parse_positive_f64)]
+    nu: Option<f64>,
+
+    /// File the rescaled results are written to; the fitted estimates and the collapse
+    /// qualities are written next to it
+    #[arg(short, long, default_value = "scaling.txt")]
+    output: PathBuf,
+}
+
+#[derive(Args)]
+struct ReweightArgs {
+    /// Time series files written by `run --save-series`
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+
+    /// Number of temperatures every reweighted curve is evaluated at
+    #[arg(long, default_value_t = 200, value_parser = clap::value_parser!(u32).range(2..))]
+    points: u32,
+
+    /// File the multi-histogram curves are written to; the single-histogram curves and the
+    /// susceptibility peaks are written next to it
+    #[arg(short, long, default_value = "reweighted.txt")]
+    output: PathBuf,
+}
+
+#[derive(Args)]
+struct BenchArgs {
+    /// Lattice size
+    #[arg(long, default_value_t = 64, value_parser = parse_lattice_size)]
+    size: usize,
+
+    /// Temperature of the sweeps
+    #[arg(long, default_value_t = 2.27, value_parser = parse_positive_f64)]
+    temperature: f64,
+
+    /// Number of sweeps timed per implementation
+    #[arg(long, default_value_t = 2_000, value_parser = clap::value_parser!(u32).range(1..))]
+    sweeps: u32,
+
+    /// Seed of the sweeps; a random one is drawn when omitted
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Random number generator used by the sweeps
+    #[arg(long, value_enum, default_value_t)]
+    rng: RngKind,
+}
+
+fn parse_lattice_size(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(size) if size >= 2 => Ok(size),
+        Ok(_) => Err(String::from("lattice size must be at least 2")),
+        Err(error) => Err(error.to_string()),
+    }
+}
+
+fn parse_magnetization(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(magnetization) if (-1.0..=1.0).contains(&magnetization) => Ok(magnetization),
+        Ok(_) => Err(String::from("magnetization must lie in [-1, 1]")),
+        Err(error) => Err(error.to_string()),
+    }
+}
+
+fn parse_fraction(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(fraction) if fraction > 0.0 && fraction < 1.0 => Ok(fraction),
+        Ok(_) => Err(String::from("must lie between 0 and 1")),
+        Err(error) => Err(error.to_string()),
+    }
+}
 
-    let magnetization = magn_sum / MAGN_STEPS as f64;
-    let susceptibility = ((lattice_size * lattice_size) as f64 / temperature)
-        * (magn_sqrt_sum / MAGN_STEPS as f64 - magnetization * magnetization);
-    (magnetization, susceptibility)
+fn parse_positive_f64(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(number) if number.is_finite() && number > 0.0 => Ok(number),
+        Ok(_) => Err(String::from("must be a positive number")),
+        Err(error) => Err(error.to_string()),
+    }
 }
 
 fn main() {
+    match Cli::parse().command {
+        Command::Run(args) => {
+            let definition = args.definition();
+            if let Err(message) = definition.validate() {
+                Cli::command()
+                    .error(ErrorKind::ValueValidation, message)
+                    .exit();
+            }
+            run(&definition.sweep(), &args.output);
+        }
+        Command::Experiment(args) => {
+            let experiment = Experiment::load(&args.file)
+                .unwrap_or_else(|message| Cli::command().error(ErrorKind::Io, message).exit());
+            let sweeps = experiment.select(&args.sweep).unwrap_or_else(|message| {
+                Cli::command()
+                    .error(ErrorKind::InvalidValue, message)
+                    .exit()
+            });
+            for (name, definition) in sweeps {
+                println!("Sweep '{}'", name);
+                run_experiment_sweep(&args.file, definition)
+                    .unwrap_or_else(|message| Cli::command().error(ErrorKind::Io, message).exit());
+            }
+        }
+        Command::Coarsen(args) => coarsen(&args),
+        Command::WangLandau(args) => {
+            if args.min_temp >= args.max_temp {
+                Cli::command()
+                    .error(
+                        ErrorKind::ValueValidation,
+                        "--min-temp must be lower than --max-temp",
+                    )
+                    .exit();
+            }
+            wang_landau(&args)
+        }
+        Command::Multicanonical(args) => {
+            if args.min_temp >= args.max_temp {
+                Cli::command()
+                    .error(
+                        ErrorKind::ValueValidation,
+                        "--min-temp must be lower than --max-temp",
+                    )
+                    .exit();
+            }
+            multicanonical(&args)
+        }
+        Command::Crossings(args) => {
+            if args.min_temp >= args.max_temp {
+                Cli::command()
+                    .error(
+                        ErrorKind::ValueValidation,
+                        "--min-temp must be lower than --max-temp",
+                    )
+                    .exit();
+            }
+            crossings(&args)
+        }
+        Command::Scaling(args) => {
+            if args.min_temp >= args.max_temp {
+                Cli::command()
+                    .error(
+                        ErrorKind::ValueValidation,
+                        "--min-temp must be lower than --max-temp",
+                    )
+                    .exit();
+            }
+            scaling(&args)
+        }
+        Command::Reweight(args) => reweight(&args),
+        Command::Bench(args) => bench(&args),
+    }
+}
+
+fn run_experiment_sweep(
+    experiment_file: &Path,
+    definition: &SweepDefinition,
+) -> Result<(), String> {
+    // keep a copy of the experiment file next to the results it produced
+    let output = &definition.output;
+    fs::create_dir_all(output)
+        .map_err(|error| format!("cannot create {}: {}", output.display(), error))?;
+    let source = fs::canonicalize(experiment_file)
+        .map_err(|error| format!("cannot read {}: {}", experiment_file.display(), error))?;
+    let destination = fs::canonicalize(output)
+        .map_err(|error| format!("cannot read {}: {}", output.display(), error))?
+        .join(source.file_name().unwrap());
+    // copying a file onto itself would truncate it
+    if source != destination {
+        fs::copy(&source, &destination).map_err(|error| {
+            format!(
+                "cannot copy {} to {}: {}",
+                source.display(),
+                destination.display(),
+                error
+            )
+        })?;
+    }
+    run(&definition.sweep(), &output.join("ising.txt"));
+    Ok(())
+}
+
+fn run(sweep: &Sweep, output: &Path) {
     let started = Instant::now();
+    println!(
+        "Seed: {}, RNG: {}, updater: {}",
+        sweep.seed, sweep.rng, sweep.updater
+    );
+
+    let progress = Mutex::new(Progress::new());
+    let on_done = |bar: &Bar| progress.lock().unwrap().inc_and_draw(bar, 1);
+    let series_output = output.with_extension("series");
+    if sweep.save_series {
+        fs::create_dir_all(&series_output).unwrap();
+    }
+    let save_series = |record: &Record, series: &TimeSeries| {
+        if sweep.save_series {
+            // the shortest representation that parses back to the temperature keeps names unique
+            let name = format!("l{}_t{}.txt", record.lattice_size, record.temperature);
+            write_time_series(
+                &series_output.join(name),
+                &sweep.metadata(),
+                record.lattice_size,
+                record.temperature,
+                series,
+            )
+            .unwrap();
+        }
+    };
 
-    // get parameters for the simulations
-    let params: Vec<(usize, f64, HashMap<i8, f64>)> = get_params();
-    let progress = Arc::new(Mutex::new(Progress::new()));
-    let bar: Bar = progress
-        .lock()
-        .unwrap()
-        .bar(params.len(), "Running simulations");
-
-    // run simulations in parallel
-    let results =
-        params
-            .par_iter()
-            .map_with(progress, |p, (lattice_size, temperature, trans_map)| {
-                let (magnetization, susceptibility) =
-                    iteration(*lattice_size, *temperature, trans_map);
-
-                p.lock().unwrap().inc_and_draw(&bar, 1);
-                (*lattice_size, *temperature, magnetization, susceptibility)
+    if let Some(swap_interval) = sweep.swap_interval {
+        let bar: Bar = progress
+            .lock()
+            .unwrap()
+            .bar(sweep.sizes.len(), "Running parallel tempering");
+        let (records, swap_rates) = run_tempering_sweep(sweep, swap_interval, || on_done(&bar))
+            .unwrap_or_else(|message| {
+                Cli::command()
+                    .error(ErrorKind::ValueValidation, message)
+                    .exit()
             });
 
-    // write the results
-    let output_file = Arc::new(Mutex::new(File::create("ising.txt").unwrap()));
-    writeln!(output_file.lock().unwrap(), "l t m s").unwrap();
-    results.for_each(|result| {
-        let (l, t, m, s) = result;
-        writeln!(
-            output_file.lock().unwrap(),
-            "{}",
-            format!("{} {:.2} {:.5} {:.5}", l, t, m, s)
-        )
-        .unwrap();
+        write_results(output, &sweep.metadata(), &records).unwrap();
+        warn_imprecise_runs(sweep, &records);
+        let swaps_output = output.with_extension("swaps.txt");
+        write_swap_rates(&swaps_output, &sweep.metadata(), &swap_rates).unwrap();
+    } else {
+        // refinement rounds may stop early, so their share of the bar is an upper bound
+        let points =
+            sweep.temperatures.len() + (sweep.refine_rounds * sweep.refine_points) as usize;
+        let tasks = sweep.sizes.len() * points;
+        let bar: Bar = progress.lock().unwrap().bar(tasks, "Running simulations");
+
+        // run simulations in parallel
+        let on_done = |record: &Record, series: &TimeSeries| {
+            save_series(record, series);
+            on_done(&bar);
+        };
+        let records = if sweep.refine_rounds > 0 {
+            run_refined_sweep(sweep, on_done)
+        } else {
+            run_sweep(sweep, on_done)
+        };
+
+        // write the results
+        write_results(output, &sweep.metadata(), &records).unwrap();
+        warn_imprecise_runs(sweep, &records);
+    }
+
+    println!("{} Done in {:?} {}", SPARKLE, started.elapsed(), ROCKET);
+}
+
+/// Points out the simulations that ran for too few autocorrelation times
+/// or missed the precision target.
+fn warn_imprecise_runs(sweep: &Sweep, records: &[Record]) {
+    for record in records {
+        if record.is_too_short() {
+            println!(
+                "Warning: L = {}, T = {:.4} is worth only {:.1} independent samples \
+                 (tau_m = {:.1}, tau_e = {:.1} sweeps); consider more measurement sweeps",
+                record.lattice_size,
+                record.temperature,
+                record.effective_samples,
+                record.magnetization_autocorrelation_time,
+                record.energy_autocorrelation_time
+            );
+        }
+        if let Some(target_error) = sweep.target_error {
+            let error = sweep.target_observable.relative_error(record);
+            if error > target_error {
+                println!(
+                    "Warning: L = {}, T = {:.4} reached a relative {} error of only {:.4} \
+                     in {} sweeps",
+                    record.lattice_size,
+                    record.temperature,
+                    sweep.target_observable,
+                    error,
+                    record.measurement_steps
+                );
+            }
+        }
+    }
+}
+
+fn coarsen(args: &CoarsenArgs) {
+    let started = Instant::now();
+    let coarsening = Coarsening {
+        lattice_size: args.size,
+        temperature: args.temperature,
+        magnetization: args.magnetization,
+        steps: args.steps,
+        measurements: args.measurements,
+        seed: args.seed.unwrap_or_else(rand::random),
+        rng: args.rng,
+    };
+    println!("Seed: {}, RNG: {}", coarsening.seed, coarsening.rng);
+
+    let (records, structure_factor) = coarsening.run();
+    let metadata = coarsening.metadata();
+    write_coarsening(&args.output, &metadata, &records).unwrap();
+    write_structure_factor(&args.structure_factor, &metadata, &structure_factor).unwrap();
+
+    println!("{} Done in {:?} {}", SPARKLE, started.elapsed(), ROCKET);
+}
+
+fn wang_landau(args: &WangLandauArgs) {
+    let started = Instant::now();
+    let wang_landau = WangLandau {
+        lattice_size: args.size,
+        flatness: args.flatness,
+        final_modification: args.final_modification,
+        check_interval: args.check_interval,
+        seed: args.seed.unwrap_or_else(rand::random),
+        rng: args.rng,
+    };
+    println!("Seed: {}, RNG: {}", wang_landau.seed, wang_landau.rng);
+
+    let mut progress = Progress::new();
+    let bar: Bar = progress.bar(wang_landau.stages(), "Refining ln f");
+    let progress = Mutex::new(progress);
+    let density_of_states = wang_landau
+        .run(|_| progress.lock().unwrap().inc_and_draw(&bar, 1))
+        .unwrap_or_else(|message| {
+            Cli::command()
+                .error(ErrorKind::ValueValidation, message)
+                .exit()
+        });
+
+    let thermodynamics: Vec<_> =
+        config::get_float_range(args.min_temp, args.max_temp, args.temp_step)
+            .into_iter()
+            .map(|temperature| density_of_states.thermodynamics(temperature))
+            .collect();
+    let metadata = wang_landau.metadata();
+    write_density_of_states(&args.output, &metadata, &density_of_states).unwrap();
+    write_thermodynamics(&args.thermodynamics, &metadata, &thermodynamics).unwrap();
+
+    println!("{} Done in {:?} {}", SPARKLE, started.elapsed(), ROCKET);
+}
+
+fn multicanonical(args: &MulticanonicalArgs) {
+    let started = Instant::now();
+    let multicanonical = Multicanonical {
+        lattice_size: args.size,
+        variable: args.variable,
+        temperature: args.temperature,
+        iterations: args.iterations,
+        iteration_sweeps: args.iteration_sweeps,
+        production_sweeps: args.production_sweeps,
+        seed: args.seed.unwrap_or_else(rand::random),
+        rng: args.rng,
+    };
+    println!("Seed: {}, RNG: {}", multicanonical.seed, multicanonical.rng);
+
+    let mut progress = Progress::new();
+    let bar: Bar = progress.bar(args.iterations as usize + 1, "Refining weights");
+    let progress = Mutex::new(progress);
+    let run = multicanonical.run(|| progress.lock().unwrap().inc_and_draw(&bar, 1));
+
+    let reweighted: Vec<_> = config::get_float_range(args.min_temp, args.max_temp, args.temp_step)
+        .into_iter()
+        .map(|temperature| run.reweight(temperature))
+        .collect();
+    let log_distribution = run.log_distribution(args.temperature);
+    if args.variable == Variable::Magnetization {
+        if let Some(tension) = get_interface_tension(&log_distribution, args.size) {
+            println!(
+                "Interface tension at T = {}: {:.6}",
+                args.temperature, tension
+            );
+        }
+    }
+
+    let metadata = multicanonical.metadata();
+    write_reweighted(&args.output, &metadata, &reweighted).unwrap();
+    write_multicanonical_weights(&args.weights, &metadata, &run).unwrap();
+    write_log_distribution(&args.distribution, &metadata, &log_distribution).unwrap();
+
+    println!("{} Done in {:?} {}", SPARKLE, started.elapsed(), ROCKET);
+}
+
+fn crossings(args: &CrossingsArgs) {
+    let mut records = Vec::new();
+    for file in &args.files {
+        let file_records = read_results(file)
+            .unwrap_or_else(|message| Cli::command().error(ErrorKind::Io, message).exit());
+        records.extend(file_records);
+    }
+
+    let crossings = find_crossings(
+        &records,
+        args.window as usize,
+        (args.min_temp, args.max_temp),
+    );
+    if crossings.is_empty() {
+        println!("No crossings found");
+    }
+    for crossing in &crossings {
+        println!(
+            "L = {} and {}: Tc = {:.5} ± {:.5}, U4 = {:.5}",
+            crossing.smaller_size,
+            crossing.larger_size,
+            crossing.temperature,
+            crossing.temperature_error,
+            crossing.binder_cumulant
+        );
+    }
+
+    let files: Vec<String> = args.files.iter().map(|f| f.display().to_string()).collect();
+    let metadata = vec![
+        ("files", files.join(", ")),
+        ("window", args.window.to_string()),
+    ];
+    write_crossings(&args.output, &metadata, &crossings).unwrap();
+}
+
+fn scaling(args: &ScalingArgs) {
+    let mut records = Vec::new();
+    for file in &args.files {
+        let file_records = read_results(file)
+            .unwrap_or_else(|message| Cli::command().error(ErrorKind::Io, message).exit());
+        records.extend(file_records);
+    }
+
+    let scaling = ising::scaling::analyze(
+        &records,
+        (args.min_temp, args.max_temp),
+        args.critical_temperature,
+        args.nu,
+    )
+    .unwrap_or_else(|message| {
+        Cli::command()
+            .error(ErrorKind::InvalidValue, message)
+            .exit()
     });
 
+    for peak in &scaling.peaks {
+        println!(
+            "L = {}: susceptibility peak at T = {:.5} ± {:.5}, s = {:.5} ± {:.5}",
+            peak.lattice_size,
+            peak.temperature,
+            peak.temperature_error,
+            peak.susceptibility,
+            peak.susceptibility_error
+        );
+    }
+    let estimates = [
+        ("Tc", &scaling.critical_temperature),
+        ("nu", &scaling.nu),
+        ("beta/nu", &scaling.beta_over_nu),
+        ("gamma/nu", &scaling.gamma_over_nu),
+    ];
+    for (name, estimate) in estimates {
+        match &estimate.fit {
+            Some(fit) => println!(
+                "{} = {:.5} ± {:.5} (chi2 = {:.3}, dof = {})",
+                name, estimate.value, estimate.error, fit.chi_squared, fit.degrees_of_freedom
+            ),
+            None => println!("{} = {:.5} (fixed)", name, estimate.value),
+        }
+    }
+    let names = ["m", "s", "u4"];
+    for (name, quality) in names.iter().zip(&scaling.collapse_quality) {
+        println!(
+            "Collapse of {}: chi2/point = {:.3} over {} points",
+            name,
+            quality.reduced_chi_squared(),
+            quality.points
+        );
+    }
+
+    let files: Vec<String> = args.files.iter().map(|f| f.display().to_string()).collect();
+    let mut metadata = vec![
+        ("files", files.join(", ")),
+        ("min_temp", args.min_temp.to_string()),
+        ("max_temp", args.max_temp.to_string()),
+    ];
+    if let Some(critical_temperature) = args.critical_temperature {
+        metadata.push(("critical_temperature", critical_temperature.to_string()));
+    }
+    if let Some(nu) = args.nu {
+        metadata.push(("nu", nu.to_string()));
+    }
+    write_collapse(&args.output, &metadata, &scaling.collapse).unwrap();
+    write_scaling_fits(&args.output.with_extension("fits.txt"), &metadata, &scaling).unwrap();
+    write_collapse_quality(
+        &args.output.with_extension("quality.txt"),
+        &metadata,
+        &scaling,
+    )
+    .unwrap();
+}
+
+fn reweight(args: &ReweightArgs) {
+    let started = Instant::now();
+    let measurements: Vec<Measurements> = args
+        .files
+        .iter()
+        .map(|file| {
+            read_time_series(file)
+                .unwrap_or_else(|message| Cli::command().error(ErrorKind::Io, message).exit())
+        })
+        .collect();
+
+    let reweighting = ising::reweighting::reweight(&measurements, args.points as usize);
+    for (lattice_size, change) in &reweighting.unconverged {
+        println!(
+            "Warning: L = {}: the multi-histogram equations did not converge in {} iterations \
+             (last change of ln Z = {:.2e})",
+            lattice_size, MAX_ITERATIONS, change
+        );
+    }
+    for peak in &reweighting.peaks {
+        println!(
+            "L = {}: susceptibility peak at T = {:.5} (single histogram {:.5}), s = {:.5}",
+            peak.lattice_size, peak.temperature, peak.single_temperature, peak.susceptibility
+        );
+    }
+
+    let metadata = vec![
+        ("files", args.files.len().to_string()),
+        ("points", args.points.to_string()),
+    ];
+    write_multi_histograms(&args.output, &metadata, &reweighting.multi).unwrap();
+    write_single_histograms(
+        &args.output.with_extension("single.txt"),
+        &metadata,
+        &reweighting.single,
+    )
+    .unwrap();
+    write_susceptibility_peaks(
+        &args.output.with_extension("peaks.txt"),
+        &metadata,
+        &reweighting.peaks,
+    )
+    .unwrap();
     println!("{} Done in {:?} {}", SPARKLE, started.elapsed(), ROCKET);
 }
+
+fn bench(args: &BenchArgs) {
+    let benchmark = Benchmark {
+        lattice_size: args.size,
+        temperature: args.temperature,
+        sweeps: args.sweeps,
+        seed: args.seed.unwrap_or_else(rand::random),
+        rng: args.rng,
+    };
+    println!("Seed: {}, RNG: {}", benchmark.seed, benchmark.rng);
+
+    let timings = benchmark.run();
+    let reference = timings[0].nanos_per_spin(&benchmark);
+    println!("implementation ns/spin speed-up identical");
+    for timing in &timings {
+        let nanos = timing.nanos_per_spin(&benchmark);
+        println!(
+            "{} {:.3} {:.2} {}",
+            timing.implementation,
+            nanos,
+            reference / nanos,
+            timing.identical
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+
+use rand::Rng;
+
+use crate::lattice::{get_neighbour_table, Lattice};
+use crate::updater::Update;
+
+/// Metropolis acceptance probabilities keyed by the energy change of a single spin flip.
+pub fn get_trans_map(temp: f64) -> HashMap<i8, f64> {
+    [
+        (-8, (8.0 / temp).exp().min(1.0)),
+        (-4, (4.0 / temp).exp().min(1.0)),
+        (0, 1.0),
+        (4, (-4.0 / temp).exp().min(1.0)),
+        (8, (-8.0 / temp).exp().min(1.0)),
+    ]
+    .iter()
+    .cloned()
+    .collect()
+}
+
+/// Flip probabilities of `trans_map` laid out as an array indexed by
+/// [`get_acceptance_index`].
+pub fn get_acceptance_array(trans_map: &HashMap<i8, f64>) -> [f64; 5] {
+    let mut acceptance = [0.0; 5];
+    for (index, local_field) in (-4..=4).step_by(2).enumerate() {
+        acceptance[index] = trans_map[&(2 * local_field)];
+    }
+    acceptance
+}
+
+/// Integer cutoffs of the probabilities in an acceptance array: a flip is accepted when a raw
+/// 64-bit random number is below its cutoff, with `u64::MAX` accepting unconditionally.
+///
+/// The cutoffs are rounded exactly as [`Rng::gen_bool`] rounds probabilities, so both make the
+/// same decisions from the same random stream. 64-bit cutoffs keep the small acceptance
+/// probabilities of low temperatures, which 32-bit ones would round to zero.
+pub fn get_acceptance_thresholds(acceptance: &[f64; 5]) -> [u64; 5] {
+    let scale = 2.0 * (1u64 << 63) as f64;
+    let mut thresholds = [0; 5];
+    for (threshold, probability) in thresholds.iter_mut().zip(acceptance) {
+        *threshold = if *probability >= 1.0 {
+            u64::MAX
+        } else {
+            (probability * scale) as u64
+        };
+    }
+    thresholds
+}
+
+/// Position in an acceptance array of a spin with the given neighbour sum,
+/// i.e. of the local field `spin * neighbour_sum` ranging over -4, -2, 0, 2, 4.
+pub fn get_acceptance_index(spin: i8, neighbour_sum: i8) -> usize {
+    ((spin * neighbour_sum + 4) / 2) as usize
+}
+
+/// Performs one single-spin sweep, visiting every site of the lattice once and flipping it
+/// with the probability `trans_map` assigns to the resulting energy change.
+/// Returns the change of the total energy over the sweep.
+///
+/// This is the straightforward reference implementation of [`recalc_lattice_with_table`],
+/// which makes exactly the same random draws and so produces identical lattices.
+pub fn recalc_lattice<R: Rng + ?Sized>(
+    lattice: &mut Lattice,
+    trans_map: &HashMap<i8, f64>,
+    rng: &mut R,
+) -> i64 {
+    let mut total_change = 0;
+    for index in 0..lattice.len() {
+        let spin = lattice.spins()[index];
+        let energy_change = 2 * spin * lattice.neighbour_sum(index);
+        if rng.gen_bool(trans_map[&energy_change]) {
+            lattice.spins_mut()[index] = -spin;
+            total_change += energy_change as i64;
+        }
+    }
+    total_change
+}
+
+/// Same as [`recalc_lattice`], looking neighbours up in a table from
+/// [`get_neighbour_table`] and flip probabilities in an array from [`get_acceptance_array`].
+pub fn recalc_lattice_with_table<R: Rng + ?Sized>(
+    lattice: &mut Lattice,
+    neighbours: &[[usize; 4]],
+    acceptance: &[f64; 5],
+    rng: &mut R,
+) -> i64 {
+    let mut total_change = 0;
+    let spins = lattice.spins_mut();
+    for (index, adjacent) in neighbours.iter().enumerate() {
+        let spin = spins[index];
+        let neighbour_sum: i8 = adjacent.iter().map(|i| spins[*i]).sum();
+        if rng.gen_bool(acceptance[get_acceptance_index(spin, neighbour_sum)]) {
+            spins[index] = -spin;
+            total_change += 2 * (spin * neighbour_sum) as i64;
+        }
+    }
+    total_change
+}
+
+/// Same as [`recalc_lattice_with_table`], deciding every flip with a single integer
+/// comparison against a cutoff from [`get_acceptance_thresholds`].
+pub fn recalc_lattice_with_thresholds<R: Rng + ?Sized>(
+    lattice: &mut Lattice,
+    neighbours: &[[usize; 4]],
+    thresholds: &[u64; 5],
+    rng: &mut R,
+) -> i64 {
+    let mut total_change = 0;
+    let spins = lattice.spins_mut();
+    for (index, adjacent) in neighbours.iter().enumerate() {
+        let spin = spins[index];
+        let neighbour_sum: i8 = adjacent.iter().map(|i| spins[*i]).sum();
+        let threshold = thresholds[get_acceptance_index(spin, neighbour_sum)];
+        if threshold == u64::MAX || rng.next_u64() < threshold {
+            spins[index] = -spin;
+            total_change += 2 * (spin * neighbour_sum) as i64;
+        }
+    }
+    total_change
+}
+
+/// Single-spin Metropolis updates.
+pub struct Metropolis {
+    neighbours: Vec<[usize; 4]>,
+    thresholds: [u64; 5],
+}
+
+impl Metropolis {
+    pub fn new(temperature: f64, lattice_size: usize) -> Self {
+        Metropolis {
+            neighbours: get_neighbour_table(lattice_size),
+            thresholds: get_acceptance_thresholds(&get_acceptance_array(&get_trans_map(
+                temperature,
+            ))),
+        }
+    }
+}
+
+impl Update for Metropolis {
+    fn sweep<R: Rng + ?Sized>(&mut self, lattice: &mut Lattice, rng: &mut R) -> i64 {
+        recalc_lattice_with_thresholds(lattice, &self.neighbours, &self.thresholds, rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::observables::get_energy;
+    use crate::rng::Xoshiro256PlusPlus;
+
+    const SIZE: usize = 12;
+    const SWEEPS: usize = 50;
+
+    /// Sweeps the same random lattice with the same random stream using each implementation,
+    /// returning the lattices after every sweep along with the energy changes reported.
+    fn run_implementations(temperature: f64) -> [Vec<(Lattice, i64)>; 3] {
+        let trans_map = get_trans_map(temperature);
+        let acceptance = get_acceptance_array(&trans_map);
+        let thresholds = get_acceptance_thresholds(&acceptance);
+        let neighbours = get_neighbour_table(SIZE);
+        [0, 1, 2].map(|implementation| {
+            let mut rng = Xoshiro256PlusPlus::seed_from_u64(7);
+            let mut lattice = Lattice::random(SIZE, &mut rng);
+            (0..SWEEPS)
+                .map(|_| {
+                    let change = match implementation {
+                        0 => recalc_lattice(&mut lattice, &trans_map, &mut rng),
+                        1 => recalc_lattice_with_table(
+                            &mut lattice,
+                            &neighbours,
+                            &acceptance,
+                            &mut rng,
+                        ),
+                        _ => recalc_lattice_with_thresholds(
+                            &mut lattice,
+                            &neighbours,
+                            &thresholds,
+                            &mut rng,
+                        ),
+                    };
+                    (lattice.clone(), change)
+                })
+                .collect()
+        })
+    }
+
+    #[test]
+    fn implementations_produce_identical_lattices() {
+        for temperature in [1.0, 2.27, 4.0] {
+            let [reference, table, thresholds] = run_implementations(temperature);
+            assert_eq!(reference, table);
+            assert_eq!(reference, thresholds);
+        }
+    }
+
+    #[test]
+    fn energy_changes_add_up() {
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(7);
+        let initial = get_energy(&Lattice::random(SIZE, &mut rng));
+        for sweeps in run_implementations(2.27) {
+            let mut energy = initial;
+            for (lattice, change) in sweeps {
+                energy += change;
+                assert_eq!(get_energy(&lattice), energy);
+            }
+        }
+    }
+}
@@ -0,0 +1,419 @@
+use std::fmt;
+
+use clap::ValueEnum;
+use rand::{Rng, SeedableRng};
+
+use crate::lattice::Lattice;
+use crate::observables::get_energy;
+use crate::rng::{RngKind, WithRng};
+
+/// Quantity whose histogram the multicanonical weights flatten.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Variable {
+    /// Total energy; the Boltzmann factor is replaced entirely by the weights
+    #[default]
+    Energy,
+    /// Total magnetization; the weights multiply the Boltzmann factor at the given temperature
+    Magnetization,
+}
+
+impl fmt::Display for Variable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.to_possible_value().unwrap().get_name())
+    }
+}
+
+/// Multicanonical simulation of a single lattice size with iteratively refined weights.
+///
+/// Configurations are sampled with probability proportional to `exp(-E / T - w(Q))`
+/// for [`Variable::Magnetization`] and to `exp(-w(Q))` for [`Variable::Energy`].
+pub struct Multicanonical {
+    pub lattice_size: usize,
+    pub variable: Variable,
+    /// Temperature of the Boltzmann factor; for [`Variable::Energy`] it only sets the initial
+    /// weights.
+    pub temperature: f64,
+    /// Number of weight refinement runs.
+    pub iterations: u32,
+    /// Sweeps per weight refinement run.
+    pub iteration_sweeps: u32,
+    /// Sweeps of the final run with frozen weights.
+    pub production_sweeps: u32,
+    pub seed: u64,
+    pub rng: RngKind,
+}
+
+/// Outcome of a multicanonical simulation.
+pub struct MulticanonicalRun {
+    pub lattice_size: usize,
+    pub variable: Variable,
+    pub temperature: f64,
+    /// Values of the flattened variable, one per bin.
+    pub bins: Vec<i64>,
+    /// Final weights `w(Q)` of every bin.
+    pub weights: Vec<f64>,
+    /// Histogram of the production run.
+    pub histogram: Vec<u64>,
+    /// Total energy and magnetization after every production sweep.
+    pub samples: Vec<(i64, i64)>,
+}
+
+/// Canonical averages per spin at a single temperature.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reweighted {
+    pub temperature: f64,
+    pub magnetization: f64,
+    pub susceptibility: f64,
+    pub energy: f64,
+    pub specific_heat: f64,
+}
+
+impl Multicanonical {
+    /// Settings recorded in the header of the output files.
+    pub fn metadata(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("seed", self.seed.to_string()),
+            ("rng", self.rng.to_string()),
+            ("lattice_size", self.lattice_size.to_string()),
+            ("variable", self.variable.to_string()),
+            ("temperature", self.temperature.to_string()),
+            ("iterations", self.iterations.to_string()),
+            ("iteration_sweeps", self.iteration_sweeps.to_string()),
+            ("production_sweeps", self.production_sweeps.to_string()),
+        ]
+    }
+
+    /// Runs the refinement iterations followed by the production run,
+    /// calling `on_iteration` after every run.
+    pub fn run<F: Fn()>(&self, on_iteration: F) -> MulticanonicalRun {
+        self.rng.dispatch(MulticanonicalTask {
+            multicanonical: self,
+            on_iteration,
+        })
+    }
+
+    /// Same as [`Multicanonical::run`], drawing random numbers from the given generator.
+    pub fn run_with<R: Rng, F: Fn()>(&self, rng: &mut R, on_iteration: F) -> MulticanonicalRun {
+        let sites = (self.lattice_size * self.lattice_size) as i64;
+        // energies are 4 apart from -2N to 2N, magnetizations 2 apart from -N to N
+        let (bins, spacing): (Vec<i64>, i64) = match self.variable {
+            Variable::Energy => ((0..=sites).map(|i| 4 * i - 2 * sites).collect(), 4),
+            Variable::Magnetization => ((0..=sites).map(|i| 2 * i - sites).collect(), 2),
+        };
+        let mut weights: Vec<f64> = match self.variable {
+            Variable::Energy => bins.iter().map(|e| *e as f64 / self.temperature).collect(),
+            Variable::Magnetization => vec![0.0; bins.len()],
+        };
+        let mut chain = Chain::new(self, spacing, rng);
+
+        for _ in 0..self.iterations {
+            let mut histogram = vec![0u64; bins.len()];
+            for _ in 0..self.iteration_sweeps {
+                chain.sweep(&weights, rng);
+                histogram[chain.bin()] += 1;
+            }
+            // w(Q) += ln H(Q) suppresses the bins that were visited most often
+            for (weight, count) in weights.iter_mut().zip(&histogram) {
+                if *count > 0 {
+                    *weight += (*count as f64).ln();
+                }
+            }
+            on_iteration();
+        }
+
+        let mut histogram = vec![0u64; bins.len()];
+        let samples = (0..self.production_sweeps)
+            .map(|_| {
+                chain.sweep(&weights, rng);
+                histogram[chain.bin()] += 1;
+                (chain.energy, chain.magnetization)
+            })
+            .collect();
+        on_iteration();
+
+        MulticanonicalRun {
+            lattice_size: self.lattice_size,
+            variable: self.variable,
+            temperature: self.temperature,
+            bins,
+            weights,
+            histogram,
+            samples,
+        }
+    }
+}
+
+/// Multicanonical simulation reporting its progress to `on_iteration`.
+struct MulticanonicalTask<'a, F> {
+    multicanonical: &'a Multicanonical,
+    on_iteration: F,
+}
+
+impl<F: Fn()> WithRng for MulticanonicalTask<'_, F> {
+    type Output = MulticanonicalRun;
+
+    fn run<R: Rng + SeedableRng + Send>(self) -> Self::Output {
+        let multicanonical = self.multicanonical;
+        multicanonical.run_with(
+            &mut R::seed_from_u64(multicanonical.seed),
+            self.on_iteration,
+        )
+    }
+}
+
+/// Single-spin Metropolis chain keeping track of the total energy and magnetization.
+struct Chain {
+    lattice: Lattice,
+    variable: Variable,
+    inverse_temperature: f64,
+    spacing: i64,
+    offset: i64,
+    energy: i64,
+    magnetization: i64,
+}
+
+impl Chain {
+    fn new<R: Rng>(settings: &Multicanonical, spacing: i64, rng: &mut R) -> Self {
+        let lattice = Lattice::random(settings.lattice_size, rng);
+        let energy = get_energy(&lattice);
+        let magnetization = lattice.total_spin();
+        let offset = match settings.variable {
+            Variable::Energy => 2 * lattice.len() as i64,
+            Variable::Magnetization => lattice.len() as i64,
+        };
+        Chain {
+            lattice,
+            variable: settings.variable,
+            inverse_temperature: 1.0 / settings.temperature,
+            spacing,
+            offset,
+            energy,
+            magnetization,
+        }
+    }
+
+    fn value(&self, energy: i64, magnetization: i64) -> i64 {
+        match self.variable {
+            Variable::Energy => energy,
+            Variable::Magnetization => magnetization,
+        }
+    }
+
+    fn bin(&self) -> usize {
+        ((self.value(self.energy, self.magnetization) + self.offset) / self.spacing) as usize
+    }
+
+    fn sweep<R: Rng>(&mut self, weights: &[f64], rng: &mut R) {
+        for index in 0..self.lattice.len() {
+            let spin = self.lattice.spins()[index];
+            let energy_change = 2 * (spin * self.lattice.neighbour_sum(index)) as i64;
+            let magnetization_change = -2 * spin as i64;
+
+            let current = self.bin();
+            let proposed = ((self.value(
+                self.energy + energy_change,
+                self.magnetization + magnetization_change,
+            ) + self.offset)
+                / self.spacing) as usize;
+            let mut log_ratio = weights[current] - weights[proposed];
+            if self.variable == Variable::Magnetization {
+                log_ratio -= self.inverse_temperature * energy_change as f64;
+            }
+            if log_ratio >= 0.0 || rng.gen::<f64>() < log_ratio.exp() {
+                self.lattice.spins_mut()[index] = -spin;
+                self.energy += energy_change;
+                self.magnetization += magnetization_change;
+            }
+        }
+    }
+}
+
+impl MulticanonicalRun {
+    /// Logarithms of the canonical weights of the production samples at `temperature`.
+    fn log_weights(&self, temperature: f64) -> Vec<f64> {
+        let spacing = self.bins[1] - self.bins[0];
+        self.samples
+            .iter()
+            .map(|(energy, magnetization)| {
+                let value = match self.variable {
+                    Variable::Energy => *energy,
+                    Variable::Magnetization => *magnetization,
+                };
+                let bin = ((value - self.bins[0]) / spacing) as usize;
+                let mut log_weight = self.weights[bin] - *energy as f64 / temperature;
+                if self.variable == Variable::Magnetization {
+                    log_weight += *energy as f64 / self.temperature;
+                }
+                log_weight
+            })
+            .collect()
+    }
+
+    /// Canonical averages at `temperature`, reweighted from the production samples.
+    ///
+    /// With [`Variable::Magnetization`] only temperatures close to the simulated one
+    /// are reliable.
+    pub fn reweight(&self, temperature: f64) -> Reweighted {
+        let sites = (self.lattice_size * self.lattice_size) as f64;
+        let log_weights = self.log_weights(temperature);
+        let max = log_weights
+            .iter()
+            .cloned()
+            .fold(f64::NEG_INFINITY, f64::max);
+
+        let mut sums = [0.0; 5];
+        for ((energy, magnetization), log_weight) in self.samples.iter().zip(&log_weights) {
+            let weight = (log_weight - max).exp();
+            let m = (*magnetization as f64 / sites).abs();
+            let e = *energy as f64 / sites;
+            sums[0] += weight;
+            sums[1] += weight * m;
+            sums[2] += weight * m * m;
+            sums[3] += weight * e;
+            sums[4] += weight * e * e;
+        }
+        let norm = sums[0];
+        let [_, m, m2, e, e2] = sums.map(|sum| sum / norm);
+        Reweighted {
+            temperature,
+            magnetization: m,
+            susceptibility: sites / temperature * (m2 - m * m),
+            energy: e,
+            specific_heat: sites / (temperature * temperature) * (e2 - e * e),
+        }
+    }
+
+    /// Canonical probability distribution of the flattened variable at `temperature`,
+    /// as `(value, ln P)` pairs for every visited bin.
+    pub fn log_distribution(&self, temperature: f64) -> Vec<(i64, f64)> {
+        let spacing = self.bins[1] - self.bins[0];
+        let mut log_probabilities = vec![f64::NEG_INFINITY; self.bins.len()];
+        let log_weights = self.log_weights(temperature);
+        let max = log_weights
+            .iter()
+            .cloned()
+            .fold(f64::NEG_INFINITY, f64::max);
+        let mut probabilities = vec![0.0; self.bins.len()];
+        for ((energy, magnetization), log_weight) in self.samples.iter().zip(&log_weights) {
+            let value = match self.variable {
+                Variable::Energy => *energy,
+                Variable::Magnetization => *magnetization,
+            };
+            probabilities[((value - self.bins[0]) / spacing) as usize] += (log_weight - max).exp();
+        }
+        let norm: f64 = probabilities.iter().sum();
+        for (log_probability, probability) in log_probabilities.iter_mut().zip(&probabilities) {
+            if *probability > 0.0 {
+                *log_probability = (probability / norm).ln();
+            }
+        }
+        self.bins
+            .iter()
+            .zip(log_probabilities)
+            .filter(|(_, log_probability)| log_probability.is_finite())
+            .map(|(value, log_probability)| (*value, log_probability))
+            .collect()
+    }
+}
+
+/// Interface tension `ln(P_max / P_min) / (2L)` from a double-peaked distribution,
+/// where `P_min` is the minimum between the two outermost maxima.
+pub fn get_interface_tension(log_distribution: &[(i64, f64)], lattice_size: usize) -> Option<f64> {
+    let half = log_distribution.len() / 2;
+    let (left, right) = log_distribution.split_at(half);
+    let peak = |side: &[(i64, f64)]| {
+        side.iter()
+            .enumerate()
+            .max_by(|a, b| a.1 .1.total_cmp(&b.1 .1))
+            .map(|(i, _)| i)
+    };
+    let (left_peak, right_peak) = (peak(left)?, half + peak(right)?);
+    let minimum = log_distribution[left_peak..=right_peak]
+        .iter()
+        .map(|(_, log_probability)| *log_probability)
+        .fold(f64::INFINITY, f64::min);
+    let maximum = log_distribution[left_peak]
+        .1
+        .max(log_distribution[right_peak].1);
+    Some((maximum - minimum) / (2.0 * lattice_size as f64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sweep::tests::get_run;
+    use crate::updater::Updater;
+
+    #[test]
+    fn reweighting_agrees_with_metropolis() {
+        let run = Multicanonical {
+            lattice_size: 8,
+            variable: Variable::Energy,
+            temperature: 2.5,
+            iterations: 20,
+            iteration_sweeps: 10_000,
+            production_sweeps: 160_000,
+            seed: 1,
+            rng: RngKind::Xoshiro,
+        }
+        .run(|| {});
+        // errors from the spread of the averages reweighted from independent blocks
+        let blocks: Vec<MulticanonicalRun> = run
+            .samples
+            .chunks(run.samples.len() / 16)
+            .map(|samples| MulticanonicalRun {
+                samples: samples.to_vec(),
+                bins: run.bins.clone(),
+                weights: run.weights.clone(),
+                histogram: Vec::new(),
+                ..run
+            })
+            .collect();
+
+        for temperature in [1.5, 3.5] {
+            let reweighted = run.reweight(temperature);
+            let block_error = |observable: fn(&Reweighted) -> f64| {
+                let values: Vec<f64> = blocks
+                    .iter()
+                    .map(|block| observable(&block.reweight(temperature)))
+                    .collect();
+                let mean = values.iter().sum::<f64>() / values.len() as f64;
+                let variance = values
+                    .iter()
+                    .map(|value| (value - mean).powi(2))
+                    .sum::<f64>()
+                    / (values.len() - 1) as f64;
+                (variance / values.len() as f64).sqrt()
+            };
+            let (expected, _) = get_run(Updater::Metropolis, temperature);
+            let observables = [
+                (
+                    "magnetization",
+                    reweighted.magnetization,
+                    block_error(|reweighted| reweighted.magnetization),
+                    expected.magnetization,
+                    expected.magnetization_error,
+                ),
+                (
+                    "energy",
+                    reweighted.energy,
+                    block_error(|reweighted| reweighted.energy),
+                    expected.energy,
+                    expected.energy_error,
+                ),
+            ];
+            for (name, value, error, expected, expected_error) in observables {
+                assert!(
+                    (value - expected).abs() <= 4.0 * error.hypot(expected_error),
+                    "{} at T = {}: {} +- {}, Metropolis gives {} +- {}",
+                    name,
+                    temperature,
+                    value,
+                    error,
+                    expected,
+                    expected_error
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use rand::Rng;
+
+use crate::lattice::{generate_lattice_with_magnetization, get_adjacent_indices};
+
+/// Number of replicas packed into a single lattice.
+pub const REPLICAS: usize = 64;
+
+/// 64 independent replicas of a square lattice, stored with multi-spin coding:
+/// bit `r` of word `i` is set when spin `i` of replica `r` points up.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PackedLattice {
+    size: usize,
+    words: Vec<u64>,
+}
+
+impl PackedLattice {
+    /// Returns replicas with every spin drawn uniformly from {-1, 1}.
+    pub fn random<R: Rng + ?Sized>(size: usize, rng: &mut R) -> Self {
+        PackedLattice {
+            size,
+            words: (0..size * size).map(|_| rng.gen()).collect(),
+        }
+    }
+
+    /// Returns random replicas, each with the given magnetization per spin.
+    pub fn with_magnetization<R: Rng + ?Sized>(
+        size: usize,
+        magnetization: f64,
+        rng: &mut R,
+    ) -> Self {
+        let mut words = vec![0; size * size];
+        for replica in 0..REPLICAS {
+            let spins = generate_lattice_with_magnetization(size, magnetization, rng);
+            pack(&mut words, replica, &spins);
+        }
+        PackedLattice { size, words }
+    }
+
+    /// Packs 64 lattices of `size * size` spins each.
+    pub fn from_lattices(size: usize, lattices: &[Vec<i8>]) -> Self {
+        assert_eq!(lattices.len(), REPLICAS, "expected {} lattices", REPLICAS);
+        let mut words = vec![0; size * size];
+        for (replica, spins) in lattices.iter().enumerate() {
+            pack(&mut words, replica, spins);
+        }
+        PackedLattice { size, words }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn words(&self) -> &[u64] {
+        &self.words
+    }
+
+    /// Spins of a single replica.
+    pub fn replica(&self, replica: usize) -> Vec<i8> {
+        self.words
+            .iter()
+            .map(|word| if word >> replica & 1 == 1 { 1 } else { -1 })
+            .collect()
+    }
+
+    /// Absolute magnetization per spin of every replica.
+    pub fn magnetizations(&self) -> Vec<f64> {
+        let ups = count_lanes(self.words.iter().cloned());
+        let sites = self.words.len() as f64;
+        ups.iter()
+            .map(|up| ((2 * up) as f64 / sites - 1.0).abs())
+            .collect()
+    }
+
+    /// Total energy `-sum s_i s_j` of every replica.
+    pub fn energies(&self) -> Vec<i64> {
+        let size = self.size;
+        let broken = count_lanes((0..self.words.len()).flat_map(|index| {
+            let [_, _, right, bottom] = get_adjacent_indices(index, size);
+            let word = self.words[index];
+            [word ^ self.words[right], word ^ self.words[bottom]]
+        }));
+        let bonds = 2 * self.words.len() as i64;
+        broken
+            .iter()
+            .map(|broken| 2 * *broken as i64 - bonds)
+            .collect()
+    }
+}
+
+fn pack(words: &mut [u64], replica: usize, spins: &[i8]) {
+    for (word, spin) in words.iter_mut().zip(spins) {
+        if *spin > 0 {
+            *word |= 1 << replica;
+        }
+    }
+}
+
+/// Counts, for every bit position, how many of the words have that bit set.
+fn count_lanes<I: Iterator<Item = u64>>(words: I) -> [u64; REPLICAS] {
+    let mut counts = [0; REPLICAS];
+    for word in words {
+        let mut remaining = word;
+        while remaining != 0 {
+            counts[remaining.trailing_zeros() as usize] += 1;
+            remaining &= remaining - 1;
+        }
+    }
+    counts
+}
+
+/// Returns a word whose bits are independently set with probability `threshold / 2^64`.
+///
+/// Every lane compares the bits of its own uniform random number with those of the
+/// threshold, most significant first, and is decided at the first differing bit; the
+/// loop ends once all 64 lanes are decided, i.e. after about 8 random words.
+pub fn get_bernoulli_word<R: Rng + ?Sized>(threshold: u64, rng: &mut R) -> u64 {
+    let mut result = 0;
+    let mut undecided = u64::MAX;
+    for bit in (0..64).rev() {
+        let random: u64 = rng.gen();
+        if threshold >> bit & 1 == 1 {
+            // lanes drawing a 0 here are below the threshold
+            result |= undecided & !random;
+            undecided &= random;
+        } else {
+            undecided &= !random;
+        }
+        if undecided == 0 {
+            break;
+        }
+    }
+    result
+}
+
+/// Acceptance threshold of an energy increase of 4, as a 64-bit fixed-point probability.
+pub fn get_packed_threshold(temp: f64) -> u64 {
+    ((-4.0 / temp).exp() * 2f64.powi(64)) as u64
+}
+
+/// Performs one Metropolis sweep of all 64 replicas at once.
+///
+/// The number of antiparallel neighbours of every lane is computed with bitwise adders;
+/// flips lowering the energy or keeping it constant are always accepted, and flips raising
+/// it by 4 or 8 are accepted with probability `p` or `p^2` for `p = exp(-4 / T)`.
+pub fn recalc_packed_lattice<R: Rng + ?Sized>(
+    lattice: &mut PackedLattice,
+    threshold: u64,
+    rng: &mut R,
+) {
+    let size = lattice.size;
+    for index in 0..lattice.words.len() {
+        let [left, top, right, bottom] = get_adjacent_indices(index, size);
+        let word = lattice.words[index];
+        let a1 = word ^ lattice.words[left];
+        let a2 = word ^ lattice.words[top];
+        let a3 = word ^ lattice.words[right];
+        let a4 = word ^ lattice.words[bottom];
+
+        // count = sum0 + 2 * (c1 + c2 + carry), where at most two of the carries are set
+        let (x1, c1) = (a1 ^ a2, a1 & a2);
+        let (x2, c2) = (a3 ^ a4, a3 & a4);
+        let (sum0, carry) = (x1 ^ x2, x1 & x2);
+        let at_least_two = c1 | c2 | carry;
+        let exactly_one = !at_least_two & sum0;
+        let none = !at_least_two & !sum0;
+
+        let mut flip = at_least_two;
+        if exactly_one | none != 0 {
+            let first = get_bernoulli_word(threshold, rng);
+            let second = if none & first != 0 {
+                get_bernoulli_word(threshold, rng)
+            } else {
+                0
+            };
+            flip |= first & (exactly_one | (none & second));
+        }
+        lattice.words[index] = word ^ flip;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::rng::Xoshiro256PlusPlus;
+    use crate::sweep::tests::assert_agrees_with_metropolis;
+    use crate::updater::Updater;
+
+    #[test]
+    fn bernoulli_words_accept_with_boltzmann_probability() {
+        const WORDS: usize = 20_000;
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(11);
+        for temperature in [1.0, 2.27, 4.0] {
+            let threshold = get_packed_threshold(temperature);
+            // energy increases of 4 take one word, increases of 8 the conjunction of two
+            for energy_change in [4.0, 8.0] {
+                let set: u32 = (0..WORDS)
+                    .map(|_| {
+                        let word = get_bernoulli_word(threshold, &mut rng);
+                        if energy_change == 4.0 {
+                            word.count_ones()
+                        } else {
+                            (word & get_bernoulli_word(threshold, &mut rng)).count_ones()
+                        }
+                    })
+                    .sum();
+                let trials = (WORDS * REPLICAS) as f64;
+                let expected = (-energy_change / temperature).exp();
+                let error = (expected * (1.0 - expected) / trials).sqrt();
+                let frequency = set as f64 / trials;
+                assert!(
+                    (frequency - expected).abs() <= 5.0 * error,
+                    "dE = {} at T = {}: {} instead of {}",
+                    energy_change,
+                    temperature,
+                    frequency,
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn agrees_with_metropolis() {
+        assert_agrees_with_metropolis(Updater::MultiSpin);
+    }
+}
@@ -0,0 +1,254 @@
+use rand::Rng;
+
+use crate::lattice::Lattice;
+use crate::results::Record;
+use crate::statistics::{
+    get_autocorrelation_time, get_bin_means, get_binning_error, get_effective_samples,
+    get_resampled_error, ErrorMethod, BINS,
+};
+
+/// Absolute magnetization per spin.
+pub fn get_magnetization(lattice: &Lattice) -> f64 {
+    (lattice.total_spin() as f64 / lattice.len() as f64).abs()
+}
+
+/// Total energy `-sum s_i s_j` over nearest-neighbour pairs.
+pub fn get_energy(lattice: &Lattice) -> i64 {
+    (0..lattice.len())
+        .map(|index| {
+            let [_, _, right, bottom] = lattice.adjacent(index);
+            let spin = lattice.spins()[index];
+            -(spin * (lattice.spins()[right] + lattice.spins()[bottom])) as i64
+        })
+        .sum()
+}
+
+/// Magnetic susceptibility from the first two moments of the magnetization per spin.
+pub fn get_susceptibility(
+    lattice_size: usize,
+    temperature: f64,
+    magnetization: f64,
+    magnetization_squared: f64,
+) -> f64 {
+    ((lattice_size * lattice_size) as f64 / temperature)
+        * (magnetization_squared - magnetization * magnetization)
+}
+
+/// Specific heat per spin from the first two moments of the total energy.
+pub fn get_specific_heat(
+    lattice_size: usize,
+    temperature: f64,
+    energy: f64,
+    energy_squared: f64,
+) -> f64 {
+    (energy_squared - energy * energy)
+        / ((lattice_size * lattice_size) as f64 * temperature * temperature)
+}
+
+/// Binder cumulant `U4 = 1 - <m^4> / (3 <m^2>^2)` from the second and fourth moments of the
+/// magnetization per spin.
+pub fn get_binder_cumulant(magnetization_squared: f64, magnetization_fourth: f64) -> f64 {
+    1.0 - magnetization_fourth / (3.0 * magnetization_squared * magnetization_squared)
+}
+
+/// Measurements taken along a single chain, in the order they were taken.
+///
+/// Measurements of several independent chains can be pooled by adding them interleaved,
+/// one from every chain in turn.
+#[derive(Clone, Debug)]
+pub struct TimeSeries {
+    /// Sweeps discarded before the first measurement.
+    thermalization: u32,
+    /// Sweeps between consecutive measurements of a chain.
+    interval: u32,
+    chains: usize,
+    magnetizations: Vec<f64>,
+    energies: Vec<i64>,
+}
+
+impl TimeSeries {
+    pub fn new(thermalization: u32, interval: u32, chains: usize) -> Self {
+        TimeSeries {
+            thermalization,
+            interval,
+            chains,
+            magnetizations: Vec::new(),
+            energies: Vec::new(),
+        }
+    }
+
+    /// Adds a measurement of the magnetization per spin and the total energy.
+    pub fn add(&mut self, magnetization: f64, energy: i64) {
+        self.magnetizations.push(magnetization);
+        self.energies.push(energy);
+    }
+
+    pub fn thermalization(&self) -> u32 {
+        self.thermalization
+    }
+
+    pub fn interval(&self) -> u32 {
+        self.interval
+    }
+
+    pub fn chains(&self) -> usize {
+        self.chains
+    }
+
+    pub fn magnetizations(&self) -> &[f64] {
+        &self.magnetizations
+    }
+
+    pub fn energies(&self) -> &[i64] {
+        &self.energies
+    }
+
+    /// Averages of the measurements taken at the given lattice size and temperature.
+    ///
+    /// Errors of the magnetization and energy come from a binning analysis; errors of the
+    /// quantities derived from several moments are resampled over bins with `error_method`,
+    /// drawing from `rng` if it needs random numbers.
+    pub fn record<R: Rng + ?Sized>(
+        &self,
+        lattice_size: usize,
+        temperature: f64,
+        error_method: ErrorMethod,
+        rng: &mut R,
+    ) -> Record {
+        let sites = (lattice_size * lattice_size) as f64;
+        let energies_per_spin: Vec<f64> = self.energies.iter().map(|e| *e as f64 / sites).collect();
+        // moments m, m^2, m^4, E, E^2 of every measurement
+        let samples: Vec<[f64; 5]> = self
+            .magnetizations
+            .iter()
+            .zip(&self.energies)
+            .map(|(m, e)| {
+                let e = *e as f64;
+                [*m, m * m, m.powi(4), e, e * e]
+            })
+            .collect();
+        let bin_means = get_bin_means(&samples, BINS);
+
+        let susceptibility = |moments: &[f64; 5]| {
+            get_susceptibility(lattice_size, temperature, moments[0], moments[1])
+        };
+        let binder_cumulant = |moments: &[f64; 5]| get_binder_cumulant(moments[1], moments[2]);
+        let specific_heat = |moments: &[f64; 5]| {
+            get_specific_heat(lattice_size, temperature, moments[3], moments[4])
+        };
+        let mut error = |estimator: &dyn Fn(&[f64; 5]) -> f64| {
+            get_resampled_error(error_method, &bin_means, estimator, rng)
+        };
+
+        let moments = get_bin_means(&samples, 1)[0];
+        let magnetization_time = get_autocorrelation_time(&self.magnetizations, self.chains);
+        let energy_time = get_autocorrelation_time(&energies_per_spin, self.chains);
+        Record {
+            lattice_size,
+            temperature,
+            magnetization: moments[0],
+            susceptibility: susceptibility(&moments),
+            binder_cumulant: binder_cumulant(&moments),
+            energy: moments[3] / sites,
+            specific_heat: specific_heat(&moments),
+            magnetization_squared: moments[1],
+            magnetization_fourth: moments[2],
+            energy_squared: moments[4] / (sites * sites),
+            magnetization_error: get_binning_error(&self.magnetizations),
+            susceptibility_error: error(&susceptibility),
+            binder_cumulant_error: error(&binder_cumulant),
+            energy_error: get_binning_error(&energies_per_spin),
+            specific_heat_error: error(&specific_heat),
+            magnetization_autocorrelation_time: magnetization_time * self.interval as f64,
+            energy_autocorrelation_time: energy_time * self.interval as f64,
+            thermalization: self.thermalization,
+            measurement_steps: (self.magnetizations.len() / self.chains) as u32 * self.interval,
+            effective_samples: get_effective_samples(
+                self.magnetizations.len(),
+                magnetization_time.max(energy_time),
+            ),
+        }
+    }
+}
+
+/// Fraction of nearest-neighbour bonds connecting opposite spins.
+pub fn get_broken_bond_density(lattice: &Lattice) -> f64 {
+    let broken: usize = (0..lattice.len())
+        .map(|index| {
+            let [_, _, right, bottom] = lattice.adjacent(index);
+            let spin = lattice.spins()[index];
+            (spin != lattice.spins()[right]) as usize + (spin != lattice.spins()[bottom]) as usize
+        })
+        .sum();
+    broken as f64 / (2 * lattice.len()) as f64
+}
+
+/// Typical linear domain size estimated from the density of domain walls.
+pub fn get_domain_size(lattice: &Lattice) -> f64 {
+    1.0 / get_broken_bond_density(lattice)
+}
+
+/// Circularly averaged structure factor `S(k) = |sum_r s_r exp(-i k r)|^2 / N`.
+///
+/// Returns `(k, S(k))` pairs for the shells `k = 2 pi n / L`, `n = 1..=L/2`,
+/// each shell collecting the wave vectors whose length rounds to `n`.
+pub fn get_structure_factor(lattice: &Lattice) -> Vec<(f64, f64)> {
+    let size = lattice.size();
+    let twiddles: Vec<(f64, f64)> = (0..size)
+        .map(|n| {
+            let angle = -2.0 * std::f64::consts::PI * n as f64 / size as f64;
+            (angle.cos(), angle.sin())
+        })
+        .collect();
+
+    // Fourier transform along the rows, then along the columns
+    let rows: Vec<(f64, f64)> = (0..size * size)
+        .map(|i| {
+            let (y, kx) = (i / size, i % size);
+            (0..size).fold((0.0, 0.0), |acc, x| {
+                let spin = lattice.spins()[y * size + x] as f64;
+                let twiddle = twiddles[(kx * x) % size];
+                (acc.0 + spin * twiddle.0, acc.1 + spin * twiddle.1)
+            })
+        })
+        .collect();
+
+    let shells = size / 2;
+    let mut sums = vec![0.0; shells + 1];
+    let mut counts = vec![0usize; shells + 1];
+    for ky in 0..size {
+        for kx in 0..size {
+            let (re, im) = (0..size).fold((0.0, 0.0), |acc, y| {
+                let value = rows[y * size + kx];
+                let twiddle = twiddles[(ky * y) % size];
+                (
+                    acc.0 + value.0 * twiddle.0 - value.1 * twiddle.1,
+                    acc.1 + value.0 * twiddle.1 + value.1 * twiddle.0,
+                )
+            });
+            let nx = kx.min(size - kx) as f64;
+            let ny = ky.min(size - ky) as f64;
+            let shell = (nx * nx + ny * ny).sqrt().round() as usize;
+            if shell >= 1 && shell <= shells {
+                sums[shell] += (re * re + im * im) / lattice.len() as f64;
+                counts[shell] += 1;
+            }
+        }
+    }
+
+    (1..=shells)
+        .filter(|shell| counts[*shell] > 0)
+        .map(|shell| {
+            let k = 2.0 * std::f64::consts::PI * shell as f64 / size as f64;
+            (k, sums[shell] / counts[shell] as f64)
+        })
+        .collect()
+}
+
+/// Domain size `2 pi / <k>` from the first moment of the structure factor.
+pub fn get_structure_factor_length(structure_factor: &[(f64, f64)]) -> f64 {
+    let (moment, norm) = structure_factor
+        .iter()
+        .fold((0.0, 0.0), |acc, (k, s)| (acc.0 + k * s, acc.1 + s));
+    2.0 * std::f64::consts::PI * norm / moment
+}
@@ -0,0 +1,124 @@
+use std::fmt;
+
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::config::Sweep;
+use crate::observables::TimeSeries;
+use crate::results::Record;
+use crate::sweep::{run_params, run_sweep, Params};
+
+/// Narrowest temperature interval that is still split in two.
+pub const MIN_INTERVAL: f64 = 0.002;
+
+/// Observable whose shape guides where temperatures are added.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RefinementObservable {
+    #[default]
+    Susceptibility,
+    SpecificHeat,
+}
+
+impl RefinementObservable {
+    pub fn value(&self, record: &Record) -> f64 {
+        match self {
+            RefinementObservable::Susceptibility => record.susceptibility,
+            RefinementObservable::SpecificHeat => record.specific_heat,
+        }
+    }
+}
+
+impl fmt::Display for RefinementObservable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.to_possible_value().unwrap().get_name())
+    }
+}
+
+/// Runs the sweep on its temperature grid, then refines the grid of every lattice size
+/// in `refine_rounds` rounds, each adding up to `refine_points` temperatures per size.
+///
+/// `on_done` is called with every finished simulation, as by [`run_sweep`]. Records are
+/// grouped by lattice size in the order of the sweep and sorted by temperature.
+pub fn run_refined_sweep<F>(sweep: &Sweep, on_done: F) -> Vec<Record>
+where
+    F: Fn(&Record, &TimeSeries) + Sync,
+{
+    let mut records = run_sweep(sweep, &on_done);
+    for _ in 0..sweep.refine_rounds {
+        let params: Vec<Params> = sweep
+            .sizes
+            .iter()
+            .flat_map(|lattice_size| {
+                let curve: Vec<&Record> = records
+                    .iter()
+                    .filter(|record| record.lattice_size == *lattice_size)
+                    .collect();
+                get_refinement_temperatures(
+                    &curve,
+                    sweep.refine_points as usize,
+                    sweep.refine_observable,
+                )
+                .into_iter()
+                .map(move |temperature| Params {
+                    lattice_size: *lattice_size,
+                    temperature,
+                })
+            })
+            .collect();
+        if params.is_empty() {
+            break;
+        }
+        records.extend(run_params(sweep, &params, &on_done));
+    }
+
+    let position = |size: usize| sweep.sizes.iter().position(|s| *s == size);
+    records.sort_by(|a, b| {
+        position(a.lattice_size)
+            .cmp(&position(b.lattice_size))
+            .then(a.temperature.total_cmp(&b.temperature))
+    });
+    records
+}
+
+/// Temperatures to add to the curve of a single lattice size: the midpoints of the (at most)
+/// `points` intervals between neighbouring temperatures that matter most.
+///
+/// The intervals on either side of the peak of the observable come first, followed by the
+/// intervals over which the observable changes the most. Intervals narrower than
+/// [`MIN_INTERVAL`] are never split.
+pub fn get_refinement_temperatures(
+    curve: &[&Record],
+    points: usize,
+    observable: RefinementObservable,
+) -> Vec<f64> {
+    let mut curve = curve.to_vec();
+    curve.sort_by(|a, b| a.temperature.total_cmp(&b.temperature));
+    let values: Vec<f64> = curve
+        .iter()
+        .map(|record| observable.value(record))
+        .collect();
+    let peak = match values.iter().enumerate().max_by(|a, b| a.1.total_cmp(b.1)) {
+        Some((peak, _)) => peak,
+        None => return Vec::new(),
+    };
+
+    // (score, interval) for the intervals between neighbouring temperatures
+    let mut intervals: Vec<(f64, usize)> = (0..curve.len().saturating_sub(1))
+        .filter(|i| curve[i + 1].temperature - curve[*i].temperature >= MIN_INTERVAL)
+        .map(|i| {
+            let score = if i + 1 == peak || i == peak {
+                f64::INFINITY
+            } else {
+                (values[i + 1] - values[i]).abs()
+            };
+            (score, i)
+        })
+        .collect();
+    intervals.sort_by(|a, b| b.0.total_cmp(&a.0));
+    intervals
+        .into_iter()
+        .take(points)
+        .map(|(_, i)| (curve[i].temperature + curve[i + 1].temperature) / 2.0)
+        .collect()
+}
@@ -0,0 +1,580 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::coarsening::CoarseningRecord;
+use crate::crossings::Crossing;
+use crate::multicanonical::{MulticanonicalRun, Reweighted};
+use crate::observables::TimeSeries;
+use crate::reweighting::{Measurements, ReweightedCurve, SusceptibilityPeak};
+use crate::scaling::{CollapsePoint, Scaling};
+use crate::statistics::MIN_EFFECTIVE_SAMPLES;
+use crate::tempering::SwapRate;
+use crate::wang_landau::{DensityOfStates, Thermodynamics};
+
+/// Measured observables of a single simulation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    pub lattice_size: usize,
+    pub temperature: f64,
+    pub magnetization: f64,
+    pub susceptibility: f64,
+    pub binder_cumulant: f64,
+    /// Mean energy per spin.
+    pub energy: f64,
+    /// Specific heat per spin.
+    pub specific_heat: f64,
+    /// Raw moments `<m^2>`, `<m^4>` and `<e^2>` the derived quantities are computed from.
+    pub magnetization_squared: f64,
+    pub magnetization_fourth: f64,
+    pub energy_squared: f64,
+    /// Standard errors of the quantities above.
+    pub magnetization_error: f64,
+    pub susceptibility_error: f64,
+    pub binder_cumulant_error: f64,
+    pub energy_error: f64,
+    pub specific_heat_error: f64,
+    /// Integrated autocorrelation times, in sweeps.
+    pub magnetization_autocorrelation_time: f64,
+    pub energy_autocorrelation_time: f64,
+    /// Number of independent measurements the run is worth, going by the longer of the
+    /// autocorrelation times.
+    pub effective_samples: f64,
+    /// Sweeps discarded before measuring.
+    pub thermalization: u32,
+    /// Sweeps covered by the measurements.
+    pub measurement_steps: u32,
+}
+
+impl Record {
+    /// Whether the run was too short, compared to its autocorrelation times,
+    /// for its errors to be trusted.
+    pub fn is_too_short(&self) -> bool {
+        self.effective_samples < MIN_EFFECTIVE_SAMPLES
+    }
+}
+
+/// Creates `path` and writes every `(key, value)` pair of `metadata` as a `# key = value`
+/// comment line.
+fn create_with_metadata(path: &Path, metadata: &[(&str, String)]) -> io::Result<BufWriter<File>> {
+    let mut output = BufWriter::new(File::create(path)?);
+    for (key, value) in metadata {
+        writeln!(output, "# {} = {}", key, value)?;
+    }
+    Ok(output)
+}
+
+/// Writes the records as a whitespace-separated table with an `l t m s u4 e c` header,
+/// followed by the raw moments `m2 m4 e2`, the standard errors `dm ds du4 de dc`, the
+/// autocorrelation times `tau_m tau_e`, the effective number of samples `n_eff`, the
+/// thermalization length `n_therm` and the number of measurement sweeps `n_meas`, and
+/// preceded by the metadata comments.
+pub fn write_results(
+    path: &Path,
+    metadata: &[(&str, String)],
+    records: &[Record],
+) -> io::Result<()> {
+    let mut output = create_with_metadata(path, metadata)?;
+    writeln!(
+        output,
+        "l t m s u4 e c m2 m4 e2 dm ds du4 de dc tau_m tau_e n_eff n_therm n_meas"
+    )?;
+    for record in records {
+        writeln!(
+            output,
+            "{} {:.4} {:.5} {:.5} {:.5} {:.5} {:.5} {:.6} {:.6} {:.6} {:.5} {:.5} {:.5} {:.5} {:.5} {:.2} {:.2} {:.1} {} {}",
+            record.lattice_size,
+            record.temperature,
+            record.magnetization,
+            record.susceptibility,
+            record.binder_cumulant,
+            record.energy,
+            record.specific_heat,
+            record.magnetization_squared,
+            record.magnetization_fourth,
+            record.energy_squared,
+            record.magnetization_error,
+            record.susceptibility_error,
+            record.binder_cumulant_error,
+            record.energy_error,
+            record.specific_heat_error,
+            record.magnetization_autocorrelation_time,
+            record.energy_autocorrelation_time,
+            record.effective_samples,
+            record.thermalization,
+            record.measurement_steps
+        )?;
+    }
+    output.flush()
+}
+
+/// Reads records written by [`write_results`], matching the columns by the names in the header.
+pub fn read_results(path: &Path) -> Result<Vec<Record>, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|error| format!("cannot read {}: {}", path.display(), error))?;
+    let mut lines = contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.starts_with('#') && !line.trim().is_empty());
+    let header: Vec<&str> = match lines.next() {
+        Some((_, line)) => line.split_whitespace().collect(),
+        None => return Err(format!("{} has no header line", path.display())),
+    };
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|column| *column == name)
+            .ok_or_else(|| format!("{} has no '{}' column", path.display(), name))
+    };
+    let columns = [
+        column("l")?,
+        column("t")?,
+        column("m")?,
+        column("s")?,
+        column("u4")?,
+        column("e")?,
+        column("c")?,
+        column("m2")?,
+        column("m4")?,
+        column("e2")?,
+        column("dm")?,
+        column("ds")?,
+        column("du4")?,
+        column("de")?,
+        column("dc")?,
+        column("tau_m")?,
+        column("tau_e")?,
+        column("n_eff")?,
+        column("n_therm")?,
+        column("n_meas")?,
+    ];
+
+    lines
+        .map(|(number, line)| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let field = |column: usize| -> Result<f64, String> {
+                fields
+                    .get(column)
+                    .and_then(|field| field.parse().ok())
+                    .ok_or_else(|| format!("{}:{}: malformed line", path.display(), number + 1))
+            };
+            Ok(Record {
+                lattice_size: field(columns[0])? as usize,
+                temperature: field(columns[1])?,
+                magnetization: field(columns[2])?,
+                susceptibility: field(columns[3])?,
+                binder_cumulant: field(columns[4])?,
+                energy: field(columns[5])?,
+                specific_heat: field(columns[6])?,
+                magnetization_squared: field(columns[7])?,
+                magnetization_fourth: field(columns[8])?,
+                energy_squared: field(columns[9])?,
+                magnetization_error: field(columns[10])?,
+                susceptibility_error: field(columns[11])?,
+                binder_cumulant_error: field(columns[12])?,
+                energy_error: field(columns[13])?,
+                specific_heat_error: field(columns[14])?,
+                magnetization_autocorrelation_time: field(columns[15])?,
+                energy_autocorrelation_time: field(columns[16])?,
+                effective_samples: field(columns[17])?,
+                thermalization: field(columns[18])? as u32,
+                measurement_steps: field(columns[19])? as u32,
+            })
+        })
+        .collect()
+}
+
+/// Writes Binder cumulant crossings as a `l1 l2 t dt u4` table.
+pub fn write_crossings(
+    path: &Path,
+    metadata: &[(&str, String)],
+    crossings: &[Crossing],
+) -> io::Result<()> {
+    let mut output = create_with_metadata(path, metadata)?;
+    writeln!(output, "l1 l2 t dt u4")?;
+    for crossing in crossings {
+        writeln!(
+            output,
+            "{} {} {:.5} {:.5} {:.5}",
+            crossing.smaller_size,
+            crossing.larger_size,
+            crossing.temperature,
+            crossing.temperature_error,
+            crossing.binder_cumulant
+        )?;
+    }
+    output.flush()
+}
+
+/// Writes a coarsening time series as a `t rho l_bb l_sf` table.
+pub fn write_coarsening(
+    path: &Path,
+    metadata: &[(&str, String)],
+    records: &[CoarseningRecord],
+) -> io::Result<()> {
+    let mut output = create_with_metadata(path, metadata)?;
+    writeln!(output, "t rho l_bb l_sf")?;
+    for record in records {
+        writeln!(
+            output,
+            "{} {:.6} {:.5} {:.5}",
+            record.step,
+            record.broken_bond_density,
+            record.domain_size,
+            record.structure_factor_length
+        )?;
+    }
+    output.flush()
+}
+
+/// Writes a circularly averaged structure factor as a `k s` table.
+pub fn write_structure_factor(
+    path: &Path,
+    metadata: &[(&str, String)],
+    structure_factor: &[(f64, f64)],
+) -> io::Result<()> {
+    let mut output = create_with_metadata(path, metadata)?;
+    writeln!(output, "k s")?;
+    for (k, s) in structure_factor {
+        writeln!(output, "{:.6} {:.6}", k, s)?;
+    }
+    output.flush()
+}
+
+/// Writes replica-exchange acceptance rates as an `l t1 t2 n rate` table.
+pub fn write_swap_rates(
+    path: &Path,
+    metadata: &[(&str, String)],
+    swap_rates: &[SwapRate],
+) -> io::Result<()> {
+    let mut output = create_with_metadata(path, metadata)?;
+    writeln!(output, "l t1 t2 n rate")?;
+    for swap_rate in swap_rates {
+        writeln!(
+            output,
+            "{} {:.4} {:.4} {} {:.5}",
+            swap_rate.lattice_size,
+            swap_rate.lower_temperature,
+            swap_rate.upper_temperature,
+            swap_rate.attempts,
+            swap_rate.rate()
+        )?;
+    }
+    output.flush()
+}
+
+/// Writes the logarithm of the density of states as an `e lng` table of total energies.
+pub fn write_density_of_states(
+    path: &Path,
+    metadata: &[(&str, String)],
+    density_of_states: &DensityOfStates,
+) -> io::Result<()> {
+    let mut output = create_with_metadata(path, metadata)?;
+    writeln!(output, "e lng")?;
+    for (energy, log_g) in density_of_states
+        .energies
+        .iter()
+        .zip(&density_of_states.log_g)
+    {
+        writeln!(output, "{} {:.8}", energy, log_g)?;
+    }
+    output.flush()
+}
+
+/// Writes thermodynamic curves per spin as a `t u f s c` table.
+pub fn write_thermodynamics(
+    path: &Path,
+    metadata: &[(&str, String)],
+    thermodynamics: &[Thermodynamics],
+) -> io::Result<()> {
+    let mut output = create_with_metadata(path, metadata)?;
+    writeln!(output, "t u f s c")?;
+    for point in thermodynamics {
+        writeln!(
+            output,
+            "{:.4} {:.6} {:.6} {:.6} {:.6}",
+            point.temperature,
+            point.internal_energy,
+            point.free_energy,
+            point.entropy,
+            point.specific_heat
+        )?;
+    }
+    output.flush()
+}
+
+/// Writes multicanonical weights and the production histogram as a `q w h` table.
+pub fn write_multicanonical_weights(
+    path: &Path,
+    metadata: &[(&str, String)],
+    run: &MulticanonicalRun,
+) -> io::Result<()> {
+    let mut output = create_with_metadata(path, metadata)?;
+    writeln!(output, "q w h")?;
+    for ((bin, weight), count) in run.bins.iter().zip(&run.weights).zip(&run.histogram) {
+        writeln!(output, "{} {:.8} {}", bin, weight, count)?;
+    }
+    output.flush()
+}
+
+/// Writes reweighted canonical averages as a `t m s e c` table.
+pub fn write_reweighted(
+    path: &Path,
+    metadata: &[(&str, String)],
+    reweighted: &[Reweighted],
+) -> io::Result<()> {
+    let mut output = create_with_metadata(path, metadata)?;
+    writeln!(output, "t m s e c")?;
+    for point in reweighted {
+        writeln!(
+            output,
+            "{:.4} {:.5} {:.5} {:.6} {:.6}",
+            point.temperature,
+            point.magnetization,
+            point.susceptibility,
+            point.energy,
+            point.specific_heat
+        )?;
+    }
+    output.flush()
+}
+
+/// Writes a logarithmic probability distribution as a `q lnp` table.
+pub fn write_log_distribution(
+    path: &Path,
+    metadata: &[(&str, String)],
+    log_distribution: &[(i64, f64)],
+) -> io::Result<()> {
+    let mut output = create_with_metadata(path, metadata)?;
+    writeln!(output, "q lnp")?;
+    for (value, log_probability) in log_distribution {
+        writeln!(output, "{} {:.8}", value, log_probability)?;
+    }
+    output.flush()
+}
+
+/// Writes the measurements of a single simulation as an `e m` table of total energies and
+/// magnetizations per spin, preceded by the metadata comments and the `lattice_size`,
+/// `temperature`, `thermalization`, `interval` and `chains` needed to read it back.
+pub fn write_time_series(
+    path: &Path,
+    metadata: &[(&str, String)],
+    lattice_size: usize,
+    temperature: f64,
+    series: &TimeSeries,
+) -> io::Result<()> {
+    let mut metadata = metadata.to_vec();
+    metadata.extend([
+        ("lattice_size", lattice_size.to_string()),
+        ("temperature", temperature.to_string()),
+        ("thermalization", series.thermalization().to_string()),
+        ("interval", series.interval().to_string()),
+        ("chains", series.chains().to_string()),
+    ]);
+    let mut output = create_with_metadata(path, &metadata)?;
+    writeln!(output, "e m")?;
+    for (energy, magnetization) in series.energies().iter().zip(series.magnetizations()) {
+        writeln!(output, "{} {}", energy, magnetization)?;
+    }
+    output.flush()
+}
+
+/// Reads measurements written by [`write_time_series`].
+pub fn read_time_series(path: &Path) -> Result<Measurements, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|error| format!("cannot read {}: {}", path.display(), error))?;
+    let metadata = |key: &str| -> Result<f64, String> {
+        contents
+            .lines()
+            .filter_map(|line| line.strip_prefix('#')?.split_once('='))
+            .find(|(name, _)| name.trim() == key)
+            .and_then(|(_, value)| value.trim().parse().ok())
+            .ok_or_else(|| format!("{} has no valid '{}' entry", path.display(), key))
+    };
+    let lattice_size = metadata("lattice_size")? as usize;
+    let temperature = metadata("temperature")?;
+    let mut series = TimeSeries::new(
+        metadata("thermalization")? as u32,
+        metadata("interval")? as u32,
+        metadata("chains")? as usize,
+    );
+
+    let mut lines = contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.starts_with('#') && !line.trim().is_empty());
+    if lines
+        .next()
+        .map(|(_, header)| header.split_whitespace().eq(["e", "m"]))
+        != Some(true)
+    {
+        return Err(format!("{} has no 'e m' header line", path.display()));
+    }
+    for (number, line) in lines {
+        let malformed = || format!("{}:{}: malformed line", path.display(), number + 1);
+        let (energy, magnetization) = line.split_once(' ').ok_or_else(malformed)?;
+        series.add(
+            magnetization.trim().parse().map_err(|_| malformed())?,
+            energy.trim().parse().map_err(|_| malformed())?,
+        );
+    }
+    if series.energies().is_empty() {
+        return Err(format!("{} has no measurements", path.display()));
+    }
+    Ok(Measurements {
+        lattice_size,
+        temperature,
+        series,
+    })
+}
+
+/// Writes single-histogram reweighted curves as an `l t0 t m s e c` table, `t0` being the
+/// simulated temperature every curve is reweighted from.
+pub fn write_single_histograms(
+    path: &Path,
+    metadata: &[(&str, String)],
+    curves: &[ReweightedCurve],
+) -> io::Result<()> {
+    let mut output = create_with_metadata(path, metadata)?;
+    writeln!(output, "l t0 t m s e c")?;
+    for curve in curves {
+        for point in &curve.points {
+            writeln!(
+                output,
+                "{} {:.4} {:.5} {:.5} {:.5} {:.6} {:.6}",
+                curve.lattice_size,
+                curve.simulated_temperature,
+                point.temperature,
+                point.magnetization,
+                point.susceptibility,
+                point.energy,
+                point.specific_heat
+            )?;
+        }
+    }
+    output.flush()
+}
+
+/// Writes multi-histogram reweighted curves of every lattice size as an `l t m s e c` table.
+pub fn write_multi_histograms(
+    path: &Path,
+    metadata: &[(&str, String)],
+    curves: &[(usize, Vec<Reweighted>)],
+) -> io::Result<()> {
+    let mut output = create_with_metadata(path, metadata)?;
+    writeln!(output, "l t m s e c")?;
+    for (lattice_size, points) in curves {
+        for point in points {
+            writeln!(
+                output,
+                "{} {:.5} {:.5} {:.5} {:.6} {:.6}",
+                lattice_size,
+                point.temperature,
+                point.magnetization,
+                point.susceptibility,
+                point.energy,
+                point.specific_heat
+            )?;
+        }
+    }
+    output.flush()
+}
+
+/// Writes susceptibility peaks as an `l t1 s1 t s` table, `t1 s1` coming from the best single
+/// histogram and `t s` from the multi-histogram combination.
+pub fn write_susceptibility_peaks(
+    path: &Path,
+    metadata: &[(&str, String)],
+    peaks: &[SusceptibilityPeak],
+) -> io::Result<()> {
+    let mut output = create_with_metadata(path, metadata)?;
+    writeln!(output, "l t1 s1 t s")?;
+    for peak in peaks {
+        writeln!(
+            output,
+            "{} {:.5} {:.5} {:.5} {:.5}",
+            peak.lattice_size,
+            peak.single_temperature,
+            peak.single_susceptibility,
+            peak.temperature,
+            peak.susceptibility
+        )?;
+    }
+    output.flush()
+}
+
+/// Writes rescaled results as an `l t x m s u4` table of the scaled temperature
+/// `x = (T - Tc) L^(1/nu)`, the scaled observables and their errors `dm ds du4`.
+pub fn write_collapse(
+    path: &Path,
+    metadata: &[(&str, String)],
+    collapse: &[CollapsePoint],
+) -> io::Result<()> {
+    let mut output = create_with_metadata(path, metadata)?;
+    writeln!(output, "l t x m s u4 dm ds du4")?;
+    for point in collapse {
+        writeln!(
+            output,
+            "{} {:.4} {:.5} {:.5} {:.5} {:.5} {:.5} {:.5} {:.5}",
+            point.lattice_size,
+            point.temperature,
+            point.scaled_temperature,
+            point.magnetization,
+            point.susceptibility,
+            point.binder_cumulant,
+            point.magnetization_error,
+            point.susceptibility_error,
+            point.binder_cumulant_error
+        )?;
+    }
+    output.flush()
+}
+
+/// Writes the estimates of a finite-size scaling analysis as a `quantity value error chi2 dof`
+/// table. Fixed estimates have no fit and are written with zero degrees of freedom.
+pub fn write_scaling_fits(
+    path: &Path,
+    metadata: &[(&str, String)],
+    scaling: &Scaling,
+) -> io::Result<()> {
+    let mut output = create_with_metadata(path, metadata)?;
+    writeln!(output, "quantity value error chi2 dof")?;
+    let estimates = [
+        ("tc", &scaling.critical_temperature),
+        ("nu", &scaling.nu),
+        ("beta_nu", &scaling.beta_over_nu),
+        ("gamma_nu", &scaling.gamma_over_nu),
+    ];
+    for (name, estimate) in estimates {
+        let (chi_squared, degrees_of_freedom) = estimate
+            .fit
+            .as_ref()
+            .map_or((0.0, 0), |fit| (fit.chi_squared, fit.degrees_of_freedom));
+        writeln!(
+            output,
+            "{} {:.5} {:.5} {:.3} {}",
+            name, estimate.value, estimate.error, chi_squared, degrees_of_freedom
+        )?;
+    }
+    output.flush()
+}
+
+/// Writes how well the magnetization, susceptibility and Binder cumulant collapse as a
+/// `quantity chi2 points` table.
+pub fn write_collapse_quality(
+    path: &Path,
+    metadata: &[(&str, String)],
+    scaling: &Scaling,
+) -> io::Result<()> {
+    let mut output = create_with_metadata(path, metadata)?;
+    writeln!(output, "quantity chi2 points")?;
+    let names = ["m", "s", "u4"];
+    for (name, quality) in names.iter().zip(&scaling.collapse_quality) {
+        writeln!(
+            output,
+            "{} {:.3} {}",
+            name, quality.chi_squared, quality.points
+        )?;
+    }
+    output.flush()
+}
@@ -0,0 +1,407 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::multicanonical::Reweighted;
+use crate::observables::TimeSeries;
+use crate::statistics::get_autocorrelation_time;
+
+/// Largest change of the log partition functions at which the multi-histogram equations
+/// are considered solved.
+const TOLERANCE: f64 = 1e-10;
+/// Most iterations spent solving the multi-histogram equations.
+pub const MAX_ITERATIONS: usize = 10_000;
+/// Golden-section steps refining a peak found on the temperature grid.
+const PEAK_STEPS: usize = 60;
+
+/// Measurements of a single simulation of a sweep, as saved with `save_series`.
+pub struct Measurements {
+    pub lattice_size: usize,
+    pub temperature: f64,
+    pub series: TimeSeries,
+}
+
+impl Measurements {
+    /// Temperatures around the simulated one that the measurements can be reweighted to:
+    /// the shift of `1 / T` may be at most the inverse of the energy fluctuations.
+    pub fn reliable_range(&self) -> (f64, f64) {
+        let energies = self.series.energies();
+        let n = energies.len() as f64;
+        let mean = energies.iter().map(|e| *e as f64).sum::<f64>() / n;
+        let variance = energies
+            .iter()
+            .map(|e| (*e as f64 - mean).powi(2))
+            .sum::<f64>()
+            / n;
+        let shift = self.temperature / variance.sqrt().max(1.0);
+        // `T / (1 + shift)` is the exact lower end and stays positive; `T (1 + shift)` falls
+        // short of the exact upper end, which is infinite for a shift of 1 or more
+        (
+            self.temperature / (1.0 + shift),
+            self.temperature * (1.0 + shift),
+        )
+    }
+
+    /// Correlated samples per independent one, `2 tau` of the energy.
+    fn statistical_inefficiency(&self) -> f64 {
+        let energies: Vec<f64> = self.series.energies().iter().map(|e| *e as f64).collect();
+        2.0 * get_autocorrelation_time(&energies, self.series.chains())
+    }
+}
+
+/// Density of states of a single lattice size combined from the energy histograms of
+/// simulations at several temperatures (Ferrenberg–Swendsen multi-histogram method, WHAM).
+///
+/// Built from a single simulation it reduces to single-histogram reweighting.
+pub struct Histogram {
+    lattice_size: usize,
+    /// Visited total energies.
+    energies: Vec<i64>,
+    /// Logarithm of the (unnormalized) density of states at every energy.
+    log_density: Vec<f64>,
+    /// Mean magnetization per spin and its square at every energy.
+    magnetizations: Vec<[f64; 2]>,
+    /// Largest change of the log partition functions in the last iteration.
+    change: f64,
+}
+
+impl Histogram {
+    /// Combines the measurements, which must all be of the same lattice size.
+    ///
+    /// Every simulation contributes in proportion to its number of independent samples,
+    /// going by the autocorrelation time of its energy.
+    pub fn new(runs: &[&Measurements]) -> Self {
+        // effective sample count, and summed m and m^2, at every energy, pooled over the runs
+        let mut sums: BTreeMap<i64, [f64; 3]> = BTreeMap::new();
+        let mut log_samples = Vec::with_capacity(runs.len());
+        for run in runs {
+            let weight = 1.0 / run.statistical_inefficiency();
+            for (energy, m) in run
+                .series
+                .energies()
+                .iter()
+                .zip(run.series.magnetizations())
+            {
+                let sum = sums.entry(*energy).or_insert([0.0; 3]);
+                sum[0] += weight;
+                sum[1] += weight * m;
+                sum[2] += weight * m * m;
+            }
+            log_samples.push((run.series.energies().len() as f64 * weight).ln());
+        }
+        let energies: Vec<i64> = sums.keys().cloned().collect();
+        let log_counts: Vec<f64> = sums.values().map(|sum| sum[0].ln()).collect();
+        let magnetizations = sums
+            .values()
+            .map(|sum| [sum[1] / sum[0], sum[2] / sum[0]])
+            .collect();
+        let betas: Vec<f64> = runs.iter().map(|run| 1.0 / run.temperature).collect();
+
+        // ln g(E) = ln H(E) - ln sum_k n_k exp(-beta_k E - ln Z_k), ln Z_k = ln sum_E g(E) exp(-beta_k E)
+        let mut log_partitions = vec![0.0; runs.len()];
+        let mut log_density = vec![0.0; energies.len()];
+        let mut change = f64::INFINITY;
+        for _ in 0..MAX_ITERATIONS {
+            for ((log_g, energy), log_count) in
+                log_density.iter_mut().zip(&energies).zip(&log_counts)
+            {
+                let terms = betas
+                    .iter()
+                    .zip(&log_samples)
+                    .zip(&log_partitions)
+                    .map(|((beta, log_n), log_z)| log_n - beta * *energy as f64 - log_z);
+                *log_g = log_count - log_sum_exp(terms);
+            }
+            change = 0.0;
+            let reference = log_sum_exp(log_density_at(&energies, &log_density, betas[0]));
+            for (log_z, beta) in log_partitions.iter_mut().zip(&betas) {
+                let updated =
+                    log_sum_exp(log_density_at(&energies, &log_density, *beta)) - reference;
+                change = change.max((updated - *log_z).abs());
+                *log_z = updated;
+            }
+            if change < TOLERANCE {
+                break;
+            }
+        }
+
+        Histogram {
+            lattice_size: runs[0].lattice_size,
+            energies,
+            log_density,
+            magnetizations,
+            change,
+        }
+    }
+
+    /// Whether the multi-histogram equations were solved within [`MAX_ITERATIONS`].
+    pub fn is_converged(&self) -> bool {
+        self.change < TOLERANCE
+    }
+
+    /// Largest change of the log partition functions in the last iteration.
+    pub fn change(&self) -> f64 {
+        self.change
+    }
+
+    /// Canonical averages per spin at `temperature`.
+    pub fn reweight(&self, temperature: f64) -> Reweighted {
+        let sites = (self.lattice_size * self.lattice_size) as f64;
+        let log_weights: Vec<f64> =
+            log_density_at(&self.energies, &self.log_density, 1.0 / temperature).collect();
+        let max = log_weights
+            .iter()
+            .cloned()
+            .fold(f64::NEG_INFINITY, f64::max);
+
+        let mut sums = [0.0; 5];
+        for ((energy, [m, m2]), log_weight) in self
+            .energies
+            .iter()
+            .zip(&self.magnetizations)
+            .zip(&log_weights)
+        {
+            let weight = (log_weight - max).exp();
+            let e = *energy as f64 / sites;
+            sums[0] += weight;
+            sums[1] += weight * m;
+            sums[2] += weight * m2;
+            sums[3] += weight * e;
+            sums[4] += weight * e * e;
+        }
+        let norm = sums[0];
+        let [_, m, m2, e, e2] = sums.map(|sum| sum / norm);
+        Reweighted {
+            temperature,
+            magnetization: m,
+            susceptibility: sites / temperature * (m2 - m * m),
+            energy: e,
+            specific_heat: sites / (temperature * temperature) * (e2 - e * e),
+        }
+    }
+
+    /// Reweighted averages at `points` evenly spaced temperatures spanning `range`.
+    pub fn curve(&self, range: (f64, f64), points: usize) -> Vec<Reweighted> {
+        get_grid(range, points)
+            .into_iter()
+            .map(|temperature| self.reweight(temperature))
+            .collect()
+    }
+
+    /// Temperature and height of the highest susceptibility peak within `range`, found on a
+    /// grid of `points` temperatures and refined by golden-section search.
+    pub fn susceptibility_peak(&self, range: (f64, f64), points: usize) -> (f64, f64) {
+        let susceptibility = |temperature: f64| self.reweight(temperature).susceptibility;
+        let grid = get_grid(range, points);
+        let highest = (0..grid.len())
+            .max_by(|a, b| susceptibility(grid[*a]).total_cmp(&susceptibility(grid[*b])))
+            .unwrap();
+        let (mut low, mut high) = (
+            grid[highest.saturating_sub(1)],
+            grid[(highest + 1).min(grid.len() - 1)],
+        );
+        let ratio = (5f64.sqrt() - 1.0) / 2.0;
+        for _ in 0..PEAK_STEPS {
+            let left = high - ratio * (high - low);
+            let right = low + ratio * (high - low);
+            if susceptibility(left) < susceptibility(right) {
+                low = left;
+            } else {
+                high = right;
+            }
+        }
+        let temperature = (low + high) / 2.0;
+        (temperature, susceptibility(temperature))
+    }
+}
+
+/// `ln g(E) - beta E` at every energy.
+fn log_density_at<'a>(
+    energies: &'a [i64],
+    log_density: &'a [f64],
+    beta: f64,
+) -> impl Iterator<Item = f64> + 'a {
+    energies
+        .iter()
+        .zip(log_density)
+        .map(move |(energy, log_g)| log_g - beta * *energy as f64)
+}
+
+/// `ln sum_i exp(x_i)` without overflow.
+fn log_sum_exp<I: Iterator<Item = f64>>(values: I) -> f64 {
+    let values: Vec<f64> = values.collect();
+    let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    max + values.iter().map(|x| (x - max).exp()).sum::<f64>().ln()
+}
+
+fn get_grid(range: (f64, f64), points: usize) -> Vec<f64> {
+    let step = (range.1 - range.0) / (points - 1) as f64;
+    (0..points).map(|i| range.0 + i as f64 * step).collect()
+}
+
+/// Reweighted averages of a single lattice size over a range of temperatures.
+pub struct ReweightedCurve {
+    pub lattice_size: usize,
+    /// Temperature of the simulation the curve is reweighted from.
+    pub simulated_temperature: f64,
+    pub points: Vec<Reweighted>,
+}
+
+/// Susceptibility maximum of a single lattice size.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SusceptibilityPeak {
+    pub lattice_size: usize,
+    /// Peak reweighted from the single simulation with the highest susceptibility,
+    /// within its reliable range.
+    pub single_temperature: f64,
+    pub single_susceptibility: f64,
+    /// Peak of the multi-histogram combination of all simulations.
+    pub temperature: f64,
+    pub susceptibility: f64,
+}
+
+/// Outcome of reweighting the measurements of a sweep.
+pub struct Reweighting {
+    /// Single-histogram curves around every simulated temperature.
+    pub single: Vec<ReweightedCurve>,
+    /// Multi-histogram curves of every lattice size, spanning its simulated temperatures and
+    /// the reliable ranges of the lowest and highest of them.
+    pub multi: Vec<(usize, Vec<Reweighted>)>,
+    pub peaks: Vec<SusceptibilityPeak>,
+    /// Lattice sizes whose multi-histogram equations were not solved within
+    /// [`MAX_ITERATIONS`], with the change of the log partition functions in the last one.
+    pub unconverged: Vec<(usize, f64)>,
+}
+
+/// Reweights every simulation over its reliable range and combines all simulations of
+/// each lattice size, evaluating every curve at `points` temperatures.
+pub fn reweight(measurements: &[Measurements], points: usize) -> Reweighting {
+    let sizes: BTreeSet<usize> = measurements.iter().map(|m| m.lattice_size).collect();
+    let mut reweighting = Reweighting {
+        single: Vec::new(),
+        multi: Vec::new(),
+        peaks: Vec::new(),
+        unconverged: Vec::new(),
+    };
+    for lattice_size in sizes {
+        let mut runs: Vec<&Measurements> = measurements
+            .iter()
+            .filter(|m| m.lattice_size == lattice_size)
+            .collect();
+        runs.sort_by(|a, b| a.temperature.total_cmp(&b.temperature));
+
+        let singles: Vec<(&Measurements, Histogram)> = runs
+            .iter()
+            .map(|run| (*run, Histogram::new(&[run])))
+            .collect();
+        for (run, histogram) in &singles {
+            reweighting.single.push(ReweightedCurve {
+                lattice_size,
+                simulated_temperature: run.temperature,
+                points: histogram.curve(run.reliable_range(), points),
+            });
+        }
+        let (highest, histogram) = singles
+            .iter()
+            .max_by(|a, b| {
+                let susceptibility = |(run, histogram): &(&Measurements, Histogram)| {
+                    histogram.reweight(run.temperature).susceptibility
+                };
+                susceptibility(a).total_cmp(&susceptibility(b))
+            })
+            .unwrap();
+        let single_peak = histogram.susceptibility_peak(highest.reliable_range(), points);
+
+        let histogram = Histogram::new(&runs);
+        if !histogram.is_converged() {
+            reweighting
+                .unconverged
+                .push((lattice_size, histogram.change()));
+        }
+        // the end runs can be reweighted beyond their temperatures, so that a peak close to
+        // either end of the sweep is not clamped to it
+        let range = (
+            runs[0].reliable_range().0,
+            runs[runs.len() - 1].reliable_range().1,
+        );
+        let peak = histogram.susceptibility_peak(range, points);
+        reweighting
+            .multi
+            .push((lattice_size, histogram.curve(range, points)));
+        reweighting.peaks.push(SusceptibilityPeak {
+            lattice_size,
+            single_temperature: single_peak.0,
+            single_susceptibility: single_peak.1,
+            temperature: peak.0,
+            susceptibility: peak.1,
+        });
+    }
+    reweighting
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::results::Record;
+    use crate::sweep::tests::get_run;
+    use crate::updater::Updater;
+
+    fn get_measurements(temperature: f64) -> (Record, Measurements) {
+        let (record, series) = get_run(Updater::Metropolis, temperature);
+        let measurements = Measurements {
+            lattice_size: 8,
+            temperature,
+            series,
+        };
+        (record, measurements)
+    }
+
+    #[test]
+    fn reweighting_to_the_simulated_temperature_gives_the_raw_averages() {
+        let (record, measurements) = get_measurements(2.3);
+        let reweighted = Histogram::new(&[&measurements]).reweight(2.3);
+        let pairs = [
+            (reweighted.magnetization, record.magnetization),
+            (reweighted.susceptibility, record.susceptibility),
+            (reweighted.energy, record.energy),
+            (reweighted.specific_heat, record.specific_heat),
+        ];
+        for (value, expected) in pairs {
+            assert!(
+                (value - expected).abs() <= 1e-9 * expected.abs(),
+                "{} instead of {}",
+                value,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn multi_histogram_matches_single_histograms_in_the_overlap() {
+        let runs = [get_measurements(2.3), get_measurements(2.4)];
+        let multi = Histogram::new(&[&runs[0].1, &runs[1].1]);
+        for (record, measurements) in &runs {
+            let single = Histogram::new(&[measurements]);
+            for temperature in [2.3, 2.35, 2.4] {
+                let (expected, value) = (single.reweight(temperature), multi.reweight(temperature));
+                let observables = [
+                    (
+                        value.magnetization,
+                        expected.magnetization,
+                        record.magnetization_error,
+                    ),
+                    (value.energy, expected.energy, record.energy_error),
+                ];
+                for (value, expected, error) in observables {
+                    assert!(
+                        (value - expected).abs() <= 4.0 * error,
+                        "T = {} from T = {}: {} instead of {} +- {}",
+                        temperature,
+                        measurements.temperature,
+                        value,
+                        expected,
+                        error
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use std::fmt;
+
+use clap::ValueEnum;
+use rand::{Rng, SeedableRng};
+use serde::Deserialize;
+
+pub use rand_chacha::ChaCha12Rng;
+pub use rand_pcg::Pcg64;
+pub use rand_xoshiro::Xoshiro256PlusPlus;
+
+/// Pseudo-random number generator driving the Monte Carlo updates.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RngKind {
+    /// Xoshiro256++, fast and non-cryptographic
+    Xoshiro,
+    /// PCG64 (XSL RR 128/64), fast and non-cryptographic
+    Pcg,
+    /// ChaCha with 12 rounds, slower but of cryptographic quality
+    #[default]
+    #[value(name = "chacha")]
+    ChaCha,
+}
+
+/// Computation generic over the type of the random number generators it seeds, run with
+/// generators of the kind chosen at runtime by [`RngKind::dispatch`].
+pub trait WithRng {
+    type Output;
+
+    fn run<R: Rng + SeedableRng + Send>(self) -> Self::Output;
+}
+
+impl RngKind {
+    /// Runs `task` with generators of this kind.
+    pub fn dispatch<T: WithRng>(&self, task: T) -> T::Output {
+        match self {
+            RngKind::Xoshiro => task.run::<Xoshiro256PlusPlus>(),
+            RngKind::Pcg => task.run::<Pcg64>(),
+            RngKind::ChaCha => task.run::<ChaCha12Rng>(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RngKind::Xoshiro => "xoshiro256++",
+            RngKind::Pcg => "pcg64",
+            RngKind::ChaCha => "chacha12",
+        }
+    }
+}
+
+impl fmt::Display for RngKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
@@ -0,0 +1,424 @@
+use std::collections::BTreeSet;
+
+use crate::fitting::{fit_line, get_weights, LineFit};
+use crate::results::Record;
+
+/// Consecutive temperatures the Binder cumulant is fitted over to find its steepest slope.
+pub const SLOPE_WINDOW: usize = 3;
+
+/// Critical temperature or exponent with its standard error and the fit it came from,
+/// which is missing for values fixed beforehand.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Estimate {
+    pub value: f64,
+    pub error: f64,
+    pub fit: Option<LineFit>,
+}
+
+impl Estimate {
+    fn fixed(value: f64) -> Self {
+        Estimate {
+            value,
+            error: 0.0,
+            fit: None,
+        }
+    }
+}
+
+/// Susceptibility maximum of a single lattice size.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Peak {
+    pub lattice_size: usize,
+    /// Vertex of the parabola through the highest susceptibility and its neighbours.
+    pub temperature: f64,
+    /// Standard error of `temperature`, propagated from the errors of the three susceptibilities.
+    pub temperature_error: f64,
+    pub susceptibility: f64,
+    pub susceptibility_error: f64,
+}
+
+/// Measurement rescaled with the critical temperature and exponents, so that the points of
+/// all lattice sizes fall onto a single curve near Tc.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CollapsePoint {
+    pub lattice_size: usize,
+    pub temperature: f64,
+    /// `(T - Tc) L^(1/nu)`
+    pub scaled_temperature: f64,
+    /// `m L^(beta/nu)`
+    pub magnetization: f64,
+    /// `chi L^(-gamma/nu)`
+    pub susceptibility: f64,
+    pub binder_cumulant: f64,
+    pub magnetization_error: f64,
+    pub susceptibility_error: f64,
+    pub binder_cumulant_error: f64,
+}
+
+/// How well the rescaled points of different lattice sizes fall onto a single curve:
+/// the squared distance of every point from the curves of the other sizes, interpolated at
+/// its scaled temperature, in units of the combined errors.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CollapseQuality {
+    pub chi_squared: f64,
+    /// Number of points compared with the curves of other sizes.
+    pub points: usize,
+}
+
+impl CollapseQuality {
+    /// Close to one for a collapse consistent with the errors.
+    pub fn reduced_chi_squared(&self) -> f64 {
+        self.chi_squared / self.points as f64
+    }
+}
+
+/// Outcome of a finite-size scaling analysis.
+pub struct Scaling {
+    pub peaks: Vec<Peak>,
+    pub critical_temperature: Estimate,
+    pub nu: Estimate,
+    pub beta_over_nu: Estimate,
+    pub gamma_over_nu: Estimate,
+    pub collapse: Vec<CollapsePoint>,
+    /// Quality of the collapse of the magnetization, susceptibility and Binder cumulant.
+    pub collapse_quality: [CollapseQuality; 3],
+}
+
+/// Estimates the critical temperature and exponents from the results of several lattice
+/// sizes within the temperature `range`, and rescales the results with them.
+///
+/// - `1/nu` is the slope of `ln max |dU4/dT|` against `ln L`, the steepest slope of the
+///   Binder cumulant being found by fitting lines through [`SLOPE_WINDOW`] consecutive
+///   temperatures,
+/// - Tc is the intercept of the susceptibility peak temperatures against `L^(-1/nu)`,
+/// - `gamma/nu` is the slope of `ln chi_max` against `ln L`,
+/// - `beta/nu` is minus the slope of `ln m(Tc)` against `ln L`, with the magnetization
+///   interpolated linearly to Tc.
+///
+/// `critical_temperature` and `nu` replace the corresponding estimates when given,
+/// e.g. with the exact values of the 2D Ising model.
+pub fn analyze(
+    records: &[Record],
+    range: (f64, f64),
+    critical_temperature: Option<f64>,
+    nu: Option<f64>,
+) -> Result<Scaling, String> {
+    let sizes: BTreeSet<usize> = records.iter().map(|r| r.lattice_size).collect();
+    let curves: Vec<(usize, Vec<&Record>)> = sizes
+        .into_iter()
+        .map(|size| {
+            let mut curve: Vec<&Record> = records
+                .iter()
+                .filter(|r| r.lattice_size == size)
+                .filter(|r| (range.0..=range.1).contains(&r.temperature))
+                .collect();
+            curve.sort_by(|a, b| a.temperature.total_cmp(&b.temperature));
+            (size, curve)
+        })
+        .collect();
+    if curves.len() < 2 {
+        return Err(String::from(
+            "finite-size scaling needs results of at least two lattice sizes",
+        ));
+    }
+    if let Some((size, _)) = curves.iter().find(|(_, curve)| curve.len() < SLOPE_WINDOW) {
+        return Err(format!(
+            "L = {} has fewer than {} temperatures in range",
+            size, SLOPE_WINDOW
+        ));
+    }
+    let log_sizes: Vec<f64> = curves.iter().map(|(size, _)| (*size as f64).ln()).collect();
+
+    let nu = match nu {
+        Some(nu) => Estimate::fixed(nu),
+        None => {
+            let slopes = curves
+                .iter()
+                .map(|(size, curve)| get_steepest_slope(*size, curve))
+                .collect::<Result<Vec<_>, _>>()?;
+            let log_slopes: Vec<f64> = slopes.iter().map(|(slope, _)| slope.ln()).collect();
+            let errors: Vec<f64> = slopes.iter().map(|(slope, error)| error / slope).collect();
+            let fit = fit_log(
+                &log_sizes,
+                &log_slopes,
+                &errors,
+                "the Binder cumulant slopes",
+            )?;
+            if fit.slope <= 0.0 {
+                return Err(String::from(
+                    "the Binder cumulant slopes do not grow with L, so nu cannot be estimated",
+                ));
+            }
+            // nu = 1 / slope
+            Estimate {
+                value: 1.0 / fit.slope,
+                error: fit.covariance[1][1].sqrt() / (fit.slope * fit.slope),
+                fit: Some(fit),
+            }
+        }
+    };
+
+    let peaks = curves
+        .iter()
+        .map(|(size, curve)| get_peak(*size, curve))
+        .collect::<Result<Vec<_>, _>>()?;
+    let critical_temperature = match critical_temperature {
+        Some(critical_temperature) => Estimate::fixed(critical_temperature),
+        None => {
+            let shifts: Vec<f64> = peaks
+                .iter()
+                .map(|peak| (peak.lattice_size as f64).powf(-1.0 / nu.value))
+                .collect();
+            let temperatures: Vec<f64> = peaks.iter().map(|peak| peak.temperature).collect();
+            let errors: Vec<f64> = peaks.iter().map(|peak| peak.temperature_error).collect();
+            let fit = fit_line(&shifts, &temperatures, get_weights(&errors))
+                .ok_or_else(|| String::from("cannot extrapolate the susceptibility peaks to Tc"))?;
+            Estimate {
+                value: fit.intercept,
+                error: fit.covariance[0][0].sqrt(),
+                fit: Some(fit),
+            }
+        }
+    };
+
+    let log_peaks: Vec<f64> = peaks.iter().map(|peak| peak.susceptibility.ln()).collect();
+    let errors: Vec<f64> = peaks
+        .iter()
+        .map(|peak| peak.susceptibility_error / peak.susceptibility)
+        .collect();
+    let fit = fit_log(&log_sizes, &log_peaks, &errors, "the susceptibility peaks")?;
+    let gamma_over_nu = Estimate {
+        value: fit.slope,
+        error: fit.covariance[1][1].sqrt(),
+        fit: Some(fit),
+    };
+
+    let magnetizations = curves
+        .iter()
+        .map(|(size, curve)| interpolate_magnetization(*size, curve, critical_temperature.value))
+        .collect::<Result<Vec<_>, _>>()?;
+    let log_magnetizations: Vec<f64> = magnetizations.iter().map(|(m, _)| m.ln()).collect();
+    let errors: Vec<f64> = magnetizations.iter().map(|(m, error)| error / m).collect();
+    let fit = fit_log(
+        &log_sizes,
+        &log_magnetizations,
+        &errors,
+        "the magnetizations at Tc",
+    )?;
+    let beta_over_nu = Estimate {
+        value: -fit.slope,
+        error: fit.covariance[1][1].sqrt(),
+        fit: Some(fit),
+    };
+
+    let collapse: Vec<CollapsePoint> = curves
+        .iter()
+        .flat_map(|(_, curve)| curve.iter())
+        .map(|record| {
+            let size = record.lattice_size as f64;
+            let magnetization_scale = size.powf(beta_over_nu.value);
+            let susceptibility_scale = size.powf(-gamma_over_nu.value);
+            CollapsePoint {
+                lattice_size: record.lattice_size,
+                temperature: record.temperature,
+                scaled_temperature: (record.temperature - critical_temperature.value)
+                    * size.powf(1.0 / nu.value),
+                magnetization: record.magnetization * magnetization_scale,
+                susceptibility: record.susceptibility * susceptibility_scale,
+                binder_cumulant: record.binder_cumulant,
+                magnetization_error: record.magnetization_error * magnetization_scale,
+                susceptibility_error: record.susceptibility_error * susceptibility_scale,
+                binder_cumulant_error: record.binder_cumulant_error,
+            }
+        })
+        .collect();
+    let collapse_quality = [
+        get_collapse_quality(&collapse, |p| (p.magnetization, p.magnetization_error)),
+        get_collapse_quality(&collapse, |p| (p.susceptibility, p.susceptibility_error)),
+        get_collapse_quality(&collapse, |p| (p.binder_cumulant, p.binder_cumulant_error)),
+    ];
+
+    Ok(Scaling {
+        peaks,
+        critical_temperature,
+        nu,
+        beta_over_nu,
+        gamma_over_nu,
+        collapse,
+        collapse_quality,
+    })
+}
+
+/// Fits a line through logarithms of a quantity against `ln L`.
+fn fit_log(
+    log_sizes: &[f64],
+    values: &[f64],
+    errors: &[f64],
+    name: &str,
+) -> Result<LineFit, String> {
+    if values.iter().any(|value| !value.is_finite()) {
+        return Err(format!("{} must all be positive", name));
+    }
+    fit_line(log_sizes, values, get_weights(errors)).ok_or_else(|| {
+        format!(
+            "cannot fit {}, which need errors or a third lattice size",
+            name
+        )
+    })
+}
+
+/// Steepest slope `|dU4/dT|` of the Binder cumulant with its standard error.
+fn get_steepest_slope(lattice_size: usize, curve: &[&Record]) -> Result<(f64, f64), String> {
+    curve
+        .windows(SLOPE_WINDOW)
+        .filter_map(|window| {
+            let temperatures: Vec<f64> = window.iter().map(|r| r.temperature).collect();
+            let cumulants: Vec<f64> = window.iter().map(|r| r.binder_cumulant).collect();
+            let errors: Vec<f64> = window.iter().map(|r| r.binder_cumulant_error).collect();
+            let fit = fit_line(&temperatures, &cumulants, get_weights(&errors))?;
+            Some((fit.slope.abs(), fit.covariance[1][1].sqrt()))
+        })
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+        .ok_or_else(|| format!("cannot fit the Binder cumulant of L = {}", lattice_size))
+}
+
+fn get_peak(lattice_size: usize, curve: &[&Record]) -> Result<Peak, String> {
+    let highest = (0..curve.len())
+        .max_by(|a, b| {
+            curve[*a]
+                .susceptibility
+                .total_cmp(&curve[*b].susceptibility)
+        })
+        .unwrap();
+    if highest == 0 || highest == curve.len() - 1 {
+        return Err(format!(
+            "the susceptibility of L = {} peaks at the edge of the temperature range",
+            lattice_size
+        ));
+    }
+    let points = [highest - 1, highest, highest + 1].map(|i| curve[i]);
+    let temperatures = points.map(|r| r.temperature);
+    let susceptibilities = points.map(|r| r.susceptibility);
+    let temperature = get_vertex(temperatures, susceptibilities).ok_or_else(|| {
+        format!(
+            "the susceptibility of L = {} is flat around its peak",
+            lattice_size
+        )
+    })?;
+    // shift of the vertex when every susceptibility is moved by its error in turn
+    let temperature_error = (0..3)
+        .map(|i| {
+            let mut shifted = susceptibilities;
+            shifted[i] += points[i].susceptibility_error;
+            get_vertex(temperatures, shifted).map_or(f64::INFINITY, |vertex| vertex - temperature)
+        })
+        .fold(0.0, f64::hypot);
+    Ok(Peak {
+        lattice_size,
+        temperature,
+        temperature_error,
+        susceptibility: susceptibilities[1],
+        susceptibility_error: points[1].susceptibility_error,
+    })
+}
+
+/// Position of the vertex of the parabola through three points, or `None` when the points
+/// are collinear and there is no parabola.
+fn get_vertex([t0, t1, t2]: [f64; 3], [s0, s1, s2]: [f64; 3]) -> Option<f64> {
+    let numerator = (t1 - t0).powi(2) * (s1 - s2) - (t1 - t2).powi(2) * (s1 - s0);
+    let denominator = (t1 - t0) * (s1 - s2) - (t1 - t2) * (s1 - s0);
+    if denominator == 0.0 {
+        return None;
+    }
+    Some(t1 - 0.5 * numerator / denominator)
+}
+
+/// Magnetization and its error at `temperature`, interpolated linearly between the
+/// neighbouring measurements.
+fn interpolate_magnetization(
+    lattice_size: usize,
+    curve: &[&Record],
+    temperature: f64,
+) -> Result<(f64, f64), String> {
+    let i = curve
+        .windows(2)
+        .position(|pair| (pair[0].temperature..=pair[1].temperature).contains(&temperature))
+        .ok_or_else(|| {
+            format!(
+                "Tc = {:.5} lies outside the temperatures of L = {}",
+                temperature, lattice_size
+            )
+        })?;
+    let (low, high) = (curve[i], curve[i + 1]);
+    let fraction = (temperature - low.temperature) / (high.temperature - low.temperature);
+    Ok((
+        low.magnetization + fraction * (high.magnetization - low.magnetization),
+        low.magnetization_error + fraction * (high.magnetization_error - low.magnetization_error),
+    ))
+}
+
+/// Compares every point with the curves of the other lattice sizes whose scaled temperatures
+/// bracket its own, interpolated linearly and averaged.
+fn get_collapse_quality<F>(collapse: &[CollapsePoint], observable: F) -> CollapseQuality
+where
+    F: Fn(&CollapsePoint) -> (f64, f64),
+{
+    let sizes: BTreeSet<usize> = collapse.iter().map(|p| p.lattice_size).collect();
+    let curve = |size: usize| -> Vec<&CollapsePoint> {
+        let mut curve: Vec<&CollapsePoint> =
+            collapse.iter().filter(|p| p.lattice_size == size).collect();
+        curve.sort_by(|a, b| a.scaled_temperature.total_cmp(&b.scaled_temperature));
+        curve
+    };
+    let curves: Vec<Vec<&CollapsePoint>> = sizes.iter().map(|size| curve(*size)).collect();
+
+    let mut quality = CollapseQuality {
+        chi_squared: 0.0,
+        points: 0,
+    };
+    for point in collapse {
+        let x = point.scaled_temperature;
+        let interpolated: Vec<(f64, f64)> = curves
+            .iter()
+            .filter(|curve| curve[0].lattice_size != point.lattice_size)
+            .filter_map(|curve| {
+                let pair = curve.windows(2).find(|pair| {
+                    pair[0].scaled_temperature <= x && x <= pair[1].scaled_temperature
+                })?;
+                let ((y0, e0), (y1, e1)) = (observable(pair[0]), observable(pair[1]));
+                let fraction = (x - pair[0].scaled_temperature)
+                    / (pair[1].scaled_temperature - pair[0].scaled_temperature);
+                Some((y0 + fraction * (y1 - y0), e0 + fraction * (e1 - e0)))
+            })
+            .collect();
+        if interpolated.is_empty() {
+            continue;
+        }
+        let count = interpolated.len() as f64;
+        let mean = interpolated.iter().map(|(y, _)| y).sum::<f64>() / count;
+        let variance = interpolated.iter().map(|(_, e)| e * e).sum::<f64>() / (count * count);
+        let (y, error) = observable(point);
+        let total_variance = error * error + variance;
+        if total_variance > 0.0 {
+            quality.chi_squared += (y - mean).powi(2) / total_variance;
+            quality.points += 1;
+        }
+    }
+    quality
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vertex_of_parabola() {
+        // s = 5 - (t - 2.3)^2
+        let temperatures = [2.1, 2.25, 2.4];
+        let susceptibilities = temperatures.map(|t: f64| 5.0 - (t - 2.3).powi(2));
+        let vertex = get_vertex(temperatures, susceptibilities).unwrap();
+        assert!((vertex - 2.3).abs() < 1e-12);
+        assert_eq!(get_vertex(temperatures, [5.0; 3]), None);
+        assert_eq!(get_vertex(temperatures, [1.0, 2.0, 3.0]), None);
+    }
+}
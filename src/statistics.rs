@@ -0,0 +1,310 @@
+use std::fmt;
+
+use clap::ValueEnum;
+use rand::Rng;
+use serde::Deserialize;
+
+/// Fewest blocks the binning analysis averages over.
+pub const MIN_BLOCKS: usize = 32;
+/// Number of bins derived quantities are resampled over.
+pub const BINS: usize = 32;
+/// Number of bootstrap resamples.
+pub const BOOTSTRAP_RESAMPLES: usize = 200;
+/// The autocorrelation function is summed up to the smallest lag of at least this many
+/// autocorrelation times.
+const WINDOW_FACTOR: f64 = 6.0;
+/// Fewest effective samples, i.e. half the run length in autocorrelation times,
+/// for which errors and autocorrelation times are considered reliable.
+pub const MIN_EFFECTIVE_SAMPLES: f64 = 50.0;
+
+/// Resampling method for the errors of quantities derived from several averages.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorMethod {
+    /// Leave-one-bin-out jackknife
+    #[default]
+    Jackknife,
+    /// Bootstrap resampling of bins
+    Bootstrap,
+}
+
+impl fmt::Display for ErrorMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.to_possible_value().unwrap().get_name())
+    }
+}
+
+pub fn get_mean(series: &[f64]) -> f64 {
+    series.iter().sum::<f64>() / series.len() as f64
+}
+
+/// Standard error of the mean of a correlated series by blocking analysis.
+///
+/// Neighbouring samples are averaged pairwise until fewer than [`MIN_BLOCKS`] blocks remain;
+/// the naive standard error grows with the block length until the blocks become uncorrelated,
+/// so the largest estimate over all levels is returned.
+pub fn get_binning_error(series: &[f64]) -> f64 {
+    let mut blocks = series.to_vec();
+    let mut error = get_standard_error(&blocks);
+    while blocks.len() / 2 >= MIN_BLOCKS {
+        blocks = blocks
+            .chunks_exact(2)
+            .map(|pair| (pair[0] + pair[1]) / 2.0)
+            .collect();
+        error = error.max(get_standard_error(&blocks));
+    }
+    error
+}
+
+/// Standard error of the mean of uncorrelated samples, infinite for fewer than two samples,
+/// whose spread is unknown.
+fn get_standard_error(samples: &[f64]) -> f64 {
+    if samples.len() < 2 {
+        return f64::INFINITY;
+    }
+    let n = samples.len() as f64;
+    let mean = get_mean(samples);
+    let variance = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    (variance / n).sqrt()
+}
+
+/// Means of `K` quantities over at most `bins` bins of consecutive samples,
+/// dropping the samples left over by the division.
+pub fn get_bin_means<const K: usize>(samples: &[[f64; K]], bins: usize) -> Vec<[f64; K]> {
+    let bin_length = (samples.len() / bins).max(1);
+    samples
+        .chunks_exact(bin_length)
+        .map(|bin| {
+            let mut means = [0.0; K];
+            for sample in bin {
+                for (mean, value) in means.iter_mut().zip(sample) {
+                    *mean += value / bin_length as f64;
+                }
+            }
+            means
+        })
+        .collect()
+}
+
+/// Averages of `K` quantities over the given bins, weighting every bin by `weights`.
+fn get_weighted_means<const K: usize>(bin_means: &[[f64; K]], weights: &[f64]) -> [f64; K] {
+    let total: f64 = weights.iter().sum();
+    let mut means = [0.0; K];
+    for (bin, weight) in bin_means.iter().zip(weights) {
+        for (mean, value) in means.iter_mut().zip(bin) {
+            *mean += weight * value / total;
+        }
+    }
+    means
+}
+
+/// Jackknife error of `estimator`, a function of the averages of `K` quantities.
+pub fn get_jackknife_error<const K: usize, F>(bin_means: &[[f64; K]], estimator: F) -> f64
+where
+    F: Fn(&[f64; K]) -> f64,
+{
+    let bins = bin_means.len();
+    let mut weights = vec![1.0; bins];
+    let estimates: Vec<f64> = (0..bins)
+        .map(|left_out| {
+            weights[left_out] = 0.0;
+            let estimate = estimator(&get_weighted_means(bin_means, &weights));
+            weights[left_out] = 1.0;
+            estimate
+        })
+        .collect();
+    let mean = get_mean(&estimates);
+    let spread: f64 = estimates.iter().map(|e| (e - mean).powi(2)).sum();
+    (spread * (bins - 1) as f64 / bins as f64).sqrt()
+}
+
+/// Bootstrap error of `estimator`, a function of the averages of `K` quantities,
+/// from [`BOOTSTRAP_RESAMPLES`] resamples of the bins.
+pub fn get_bootstrap_error<const K: usize, F, R>(
+    bin_means: &[[f64; K]],
+    estimator: F,
+    rng: &mut R,
+) -> f64
+where
+    F: Fn(&[f64; K]) -> f64,
+    R: Rng + ?Sized,
+{
+    let bins = bin_means.len();
+    let estimates: Vec<f64> = (0..BOOTSTRAP_RESAMPLES)
+        .map(|_| {
+            let mut weights = vec![0.0; bins];
+            for _ in 0..bins {
+                weights[rng.gen_range(0..bins)] += 1.0;
+            }
+            estimator(&get_weighted_means(bin_means, &weights))
+        })
+        .collect();
+    get_standard_error(&estimates) * (BOOTSTRAP_RESAMPLES as f64).sqrt()
+}
+
+/// Error of `estimator`, a function of the averages of `K` quantities, by the given method.
+pub fn get_resampled_error<const K: usize, F, R>(
+    method: ErrorMethod,
+    bin_means: &[[f64; K]],
+    estimator: F,
+    rng: &mut R,
+) -> f64
+where
+    F: Fn(&[f64; K]) -> f64,
+    R: Rng + ?Sized,
+{
+    match method {
+        ErrorMethod::Jackknife => get_jackknife_error(bin_means, estimator),
+        ErrorMethod::Bootstrap => get_bootstrap_error(bin_means, estimator, rng),
+    }
+}
+
+/// Integrated autocorrelation time `1/2 + sum_t rho(t)`, in samples, of `chains` independent
+/// series stored interleaved, i.e. sample `i` of chain `c` at `series[i * chains + c]`.
+///
+/// The normalized autocorrelation `rho(t)` is averaged over the chains and summed up to a
+/// window chosen automatically (Sokal): the smallest lag `W` with `W >= 6 tau(W)`. If no such
+/// lag exists below half the chain length the estimate is unreliable, which shows as a small
+/// number of effective samples.
+pub fn get_autocorrelation_time(series: &[f64], chains: usize) -> f64 {
+    let length = series.len() / chains;
+    let mean = get_mean(series);
+    let autocovariance = |lag: usize| -> f64 {
+        let sum: f64 = (0..(length - lag) * chains)
+            .map(|i| (series[i] - mean) * (series[i + lag * chains] - mean))
+            .sum();
+        sum / ((length - lag) * chains) as f64
+    };
+    let variance = autocovariance(0);
+    if variance <= 0.0 {
+        // a series that never changes carries no information about its correlations
+        return 0.5;
+    }
+
+    let mut time = 0.5;
+    for lag in 1..length / 2 {
+        time += autocovariance(lag) / variance;
+        if lag as f64 >= WINDOW_FACTOR * time {
+            break;
+        }
+    }
+    time.max(0.5)
+}
+
+/// Number of independent samples a series of `samples` correlated ones is worth.
+pub fn get_effective_samples(samples: usize, autocorrelation_time: f64) -> f64 {
+    samples as f64 / (2.0 * autocorrelation_time)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::rng::Xoshiro256PlusPlus;
+
+    /// Stationary AR(1) series `x(t) = rho x(t-1) + sqrt(1 - rho^2) noise(t)` of unit variance,
+    /// whose integrated autocorrelation time is `(1 + rho) / (2 (1 - rho))`.
+    fn get_ar1_series<R: Rng>(rho: f64, length: usize, rng: &mut R) -> Vec<f64> {
+        let scale = (1.0 - rho * rho).sqrt() * 3f64.sqrt();
+        let mut x = rng.gen_range(-3f64.sqrt()..3f64.sqrt());
+        (0..length)
+            .map(|_| {
+                x = rho * x + scale * rng.gen_range(-1.0..1.0);
+                x
+            })
+            .collect()
+    }
+
+    fn get_uniform_samples<R: Rng>(length: usize, rng: &mut R) -> Vec<[f64; 2]> {
+        (0..length)
+            .map(|_| {
+                let x: f64 = rng.gen();
+                [x, x * x]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bin_means_drop_leftover_samples() {
+        let samples: Vec<[f64; 1]> = (0..10).map(|i| [i as f64]).collect();
+        assert_eq!(get_bin_means(&samples, 3), vec![[1.0], [4.0], [7.0]]);
+        assert_eq!(get_bin_means(&samples, 1), vec![[4.5]]);
+    }
+
+    #[test]
+    fn jackknife_error_of_the_mean_is_the_standard_error() {
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(1);
+        let bin_means = get_bin_means(&get_uniform_samples(3_200, &mut rng), BINS);
+        let means: Vec<f64> = bin_means.iter().map(|bin| bin[0]).collect();
+        let jackknife = get_jackknife_error(&bin_means, |moments| moments[0]);
+        assert!((jackknife - get_standard_error(&means)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn bootstrap_agrees_with_jackknife() {
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(2);
+        let bin_means = get_bin_means(&get_uniform_samples(32_000, &mut rng), BINS);
+        let variance = |moments: &[f64; 2]| moments[1] - moments[0] * moments[0];
+        let jackknife = get_jackknife_error(&bin_means, variance);
+        let bootstrap = get_bootstrap_error(&bin_means, variance, &mut rng);
+        assert!(
+            (bootstrap / jackknife - 1.0).abs() < 0.2,
+            "bootstrap {} against jackknife {}",
+            bootstrap,
+            jackknife
+        );
+    }
+
+    #[test]
+    fn binning_recovers_the_error_of_a_correlated_series() {
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(3);
+        let (rho, length) = (0.9, 1 << 18);
+        let series = get_ar1_series(rho, length, &mut rng);
+        let time = (1.0 + rho) / (2.0 * (1.0 - rho));
+        let expected = (2.0 * time / length as f64).sqrt();
+        let error = get_binning_error(&series);
+        assert!(
+            (error / expected - 1.0).abs() < 0.25,
+            "{} instead of {}",
+            error,
+            expected
+        );
+        // the naive error misses the correlations
+        assert!(get_standard_error(&series) < 0.5 * expected);
+    }
+
+    #[test]
+    fn autocorrelation_time_of_a_correlated_series() {
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(4);
+        let rho = 0.8;
+        let expected = (1.0 + rho) / (2.0 * (1.0 - rho));
+        let time = get_autocorrelation_time(&get_ar1_series(rho, 200_000, &mut rng), 1);
+        assert!(
+            (time / expected - 1.0).abs() < 0.1,
+            "{} instead of {}",
+            time,
+            expected
+        );
+    }
+
+    #[test]
+    fn autocorrelation_time_of_interleaved_chains() {
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(5);
+        let (rho, chains, length) = (0.8, 4, 50_000);
+        let expected = (1.0 + rho) / (2.0 * (1.0 - rho));
+        let chain_series: Vec<Vec<f64>> = (0..chains)
+            .map(|_| get_ar1_series(rho, length, &mut rng))
+            .collect();
+        let series: Vec<f64> = (0..length)
+            .flat_map(|i| chain_series.iter().map(move |chain| chain[i]))
+            .collect();
+        let time = get_autocorrelation_time(&series, chains);
+        assert!(
+            (time / expected - 1.0).abs() < 0.1,
+            "{} instead of {}",
+            time,
+            expected
+        );
+    }
+}
@@ -0,0 +1,337 @@
+use rand::{Rng, SeedableRng};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+
+use crate::config::Sweep;
+use crate::equilibration::equilibrate;
+use crate::lattice::Lattice;
+use crate::multispin::{get_packed_threshold, recalc_packed_lattice, PackedLattice, REPLICAS};
+use crate::observables::{get_energy, get_magnetization, TimeSeries};
+use crate::results::Record;
+use crate::rng::WithRng;
+use crate::updater::{AnyUpdater, Update, Updater};
+
+/// Measurement sweeps after which a precision target is checked for the first time.
+const FIRST_PRECISION_CHECK: u32 = 1024;
+
+/// Parameters of a single simulation within a sweep.
+pub struct Params {
+    pub lattice_size: usize,
+    pub temperature: f64,
+}
+
+pub fn get_params(sweep: &Sweep) -> Vec<Params> {
+    sweep
+        .sizes
+        .iter()
+        .flat_map(|lattice_size| {
+            sweep.temperatures.iter().map(move |temp| Params {
+                lattice_size: *lattice_size,
+                temperature: *temp,
+            })
+        })
+        .collect()
+}
+
+/// Derives the seed of the random stream used by a single simulation.
+///
+/// The seed depends only on the master seed, the lattice size and the temperature,
+/// so results do not depend on the order in which simulations are scheduled.
+pub fn task_seed(master_seed: u64, lattice_size: usize, temperature: f64) -> u64 {
+    [lattice_size as u64, temperature.to_bits()]
+        .iter()
+        .fold(splitmix64(master_seed), |seed, value| {
+            splitmix64(seed ^ value)
+        })
+}
+
+fn splitmix64(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Thermalizes a random lattice, then measures its magnetization, energy and their fluctuations.
+/// Returns the averages along with the measurements they were taken from.
+pub fn iteration(sweep: &Sweep, params: &Params) -> (Record, TimeSeries) {
+    sweep.rng.dispatch(SimulationTask { sweep, params })
+}
+
+/// Simulation of a single lattice size and temperature, seeded from the master seed.
+struct SimulationTask<'a> {
+    sweep: &'a Sweep,
+    params: &'a Params,
+}
+
+impl WithRng for SimulationTask<'_> {
+    type Output = (Record, TimeSeries);
+
+    fn run<R: Rng + SeedableRng + Send>(self) -> Self::Output {
+        let params = self.params;
+        let seed = task_seed(self.sweep.seed, params.lattice_size, params.temperature);
+        simulate(self.sweep, params, &mut R::seed_from_u64(seed))
+    }
+}
+
+/// Same as [`iteration`], drawing random numbers from the given generator.
+pub fn simulate<R: Rng + SeedableRng + Send>(
+    sweep: &Sweep,
+    params: &Params,
+    rng: &mut R,
+) -> (Record, TimeSeries) {
+    if sweep.updater == Updater::MultiSpin {
+        return run_packed_chains(sweep, params, rng);
+    }
+    let updater = AnyUpdater::<R>::new(sweep.updater, params.temperature, params.lattice_size, rng)
+        .expect("multi-spin runs are handled above and sizes are validated with the sweep");
+    run_chain(sweep, params, updater, rng)
+}
+
+/// Initial lattice of every simulation in the sweep.
+pub fn initial_lattice<R: Rng + ?Sized>(
+    sweep: &Sweep,
+    lattice_size: usize,
+    rng: &mut R,
+) -> Lattice {
+    match sweep.initial_magnetization {
+        Some(magnetization) => Lattice::with_magnetization(lattice_size, magnetization, rng),
+        None => Lattice::random(lattice_size, rng),
+    }
+}
+
+/// Same as [`simulate`], moving the lattice with the given update rule.
+///
+/// The energy is computed once for the initial lattice and then followed through the
+/// energy changes reported by the updater.
+pub fn run_chain<U: Update, R: Rng + SeedableRng>(
+    sweep: &Sweep,
+    params: &Params,
+    mut updater: U,
+    rng: &mut R,
+) -> (Record, TimeSeries) {
+    let mut lattice = initial_lattice(sweep, params.lattice_size, rng);
+    let mut energy = get_energy(&lattice);
+
+    let thermalization = if sweep.auto_equilibration {
+        equilibrate(sweep.initial_steps, || {
+            energy += updater.sweep(&mut lattice, rng);
+            energy as f64
+        })
+    } else {
+        (0..sweep.initial_steps).for_each(|_| {
+            energy += updater.sweep(&mut lattice, rng);
+        });
+        sweep.initial_steps
+    };
+
+    let mut series = TimeSeries::new(thermalization, sweep.magn_calc_step, 1);
+    measure(sweep, params, &mut series, rng, |i, series, rng| {
+        energy += updater.sweep(&mut lattice, rng);
+        if i % sweep.magn_calc_step == 0 {
+            series.add(get_magnetization(&lattice), energy);
+        }
+    });
+    let record = series.record(
+        params.lattice_size,
+        params.temperature,
+        sweep.error_method,
+        rng,
+    );
+    (record, series)
+}
+
+/// Same as [`simulate`], running 64 bit-packed replicas with multi-spin coded Metropolis
+/// updates and pooling the measurements of all of them.
+pub fn run_packed_chains<R: Rng + SeedableRng>(
+    sweep: &Sweep,
+    params: &Params,
+    rng: &mut R,
+) -> (Record, TimeSeries) {
+    let mut lattice = match sweep.initial_magnetization {
+        Some(magnetization) => {
+            PackedLattice::with_magnetization(params.lattice_size, magnetization, rng)
+        }
+        None => PackedLattice::random(params.lattice_size, rng),
+    };
+    let threshold = get_packed_threshold(params.temperature);
+
+    let thermalization = if sweep.auto_equilibration {
+        // the drift test follows the energy averaged over the replicas
+        equilibrate(sweep.initial_steps, || {
+            recalc_packed_lattice(&mut lattice, threshold, rng);
+            lattice.energies().iter().sum::<i64>() as f64 / REPLICAS as f64
+        })
+    } else {
+        (0..sweep.initial_steps).for_each(|_| {
+            recalc_packed_lattice(&mut lattice, threshold, rng);
+        });
+        sweep.initial_steps
+    };
+
+    let mut series = TimeSeries::new(thermalization, sweep.magn_calc_step, REPLICAS);
+    measure(sweep, params, &mut series, rng, |i, series, rng| {
+        recalc_packed_lattice(&mut lattice, threshold, rng);
+        if i % sweep.magn_calc_step == 0 {
+            // the energies of all replicas come from a handful of word operations per site,
+            // which is cheaper than following them through the flips of every lane
+            for (magnetization, energy) in
+                lattice.magnetizations().into_iter().zip(lattice.energies())
+            {
+                series.add(magnetization, energy);
+            }
+        }
+    });
+    let record = series.record(
+        params.lattice_size,
+        params.temperature,
+        sweep.error_method,
+        rng,
+    );
+    (record, series)
+}
+
+/// Performs the measurement sweeps numbered `0..later_steps`, each with `advance`.
+///
+/// With a precision target the sweeps stop early once the target observable is known well
+/// enough. The error is checked after `1024, 2048, 4096, ...` sweeps (rounded up to whole
+/// measurement intervals), and only trusted once the run is long enough compared to its
+/// autocorrelation times. The errors at the checks are resampled with a stream of their own,
+/// so the sweeps are the same as without a precision target.
+fn measure<R, F>(
+    sweep: &Sweep,
+    params: &Params,
+    series: &mut TimeSeries,
+    rng: &mut R,
+    mut advance: F,
+) where
+    R: Rng + SeedableRng,
+    F: FnMut(u32, &mut TimeSeries, &mut R),
+{
+    let seed = task_seed(sweep.seed, params.lattice_size, params.temperature);
+    let mut check_rng = R::seed_from_u64(splitmix64(seed));
+    let mut check = FIRST_PRECISION_CHECK;
+    for i in 0..sweep.later_steps {
+        advance(i, series, rng);
+        let done = i + 1;
+        let target_error = match sweep.target_error {
+            Some(target_error) if done >= check && done % sweep.magn_calc_step == 0 => target_error,
+            _ => continue,
+        };
+        let record = series.record(
+            params.lattice_size,
+            params.temperature,
+            sweep.error_method,
+            &mut check_rng,
+        );
+        if !record.is_too_short() && sweep.target_observable.relative_error(&record) <= target_error
+        {
+            return;
+        }
+        while check <= done && check < u32::MAX {
+            check = check.saturating_mul(2);
+        }
+    }
+}
+
+/// Runs every simulation of the sweep in parallel.
+///
+/// `on_done` is called with every finished simulation and its measurements, e.g. to drive
+/// a progress bar or save the time series. Records are returned in the order of [`get_params`].
+pub fn run_sweep<F>(sweep: &Sweep, on_done: F) -> Vec<Record>
+where
+    F: Fn(&Record, &TimeSeries) + Sync,
+{
+    run_params(sweep, &get_params(sweep), on_done)
+}
+
+/// Same as [`run_sweep`], running only the simulations with the given parameters.
+pub fn run_params<F>(sweep: &Sweep, params: &[Params], on_done: F) -> Vec<Record>
+where
+    F: Fn(&Record, &TimeSeries) + Sync,
+{
+    params
+        .par_iter()
+        .map(|params| {
+            let (record, series) = iteration(sweep, params);
+            on_done(&record, &series);
+            record
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::config::TargetObservable;
+    use crate::refinement::RefinementObservable;
+    use crate::rng::RngKind;
+    use crate::statistics::ErrorMethod;
+
+    /// Averages and measurements of an ordered 8x8 lattice moved with `updater` at
+    /// `temperature`.
+    pub(crate) fn get_run(updater: Updater, temperature: f64) -> (Record, TimeSeries) {
+        let sweep = Sweep {
+            sizes: vec![8],
+            temperatures: vec![temperature],
+            initial_steps: 1_000,
+            auto_equilibration: false,
+            later_steps: 20_000,
+            magn_calc_step: 1,
+            target_error: None,
+            target_observable: TargetObservable::default(),
+            seed: 3,
+            rng: RngKind::Xoshiro,
+            updater,
+            initial_magnetization: Some(1.0),
+            swap_interval: None,
+            error_method: ErrorMethod::default(),
+            refine_rounds: 0,
+            refine_points: 0,
+            refine_observable: RefinementObservable::default(),
+            save_series: false,
+        };
+        let params = Params {
+            lattice_size: 8,
+            temperature,
+        };
+        iteration(&sweep, &params)
+    }
+
+    /// Asserts that the magnetization and energy of `updater` agree with those of
+    /// scalar Metropolis updates within four standard errors.
+    pub(crate) fn assert_agrees_with_metropolis(updater: Updater) {
+        for temperature in [1.5, 3.5] {
+            let (expected, _) = get_run(Updater::Metropolis, temperature);
+            let (record, _) = get_run(updater, temperature);
+            let observables = [
+                (
+                    "magnetization",
+                    record.magnetization,
+                    record.magnetization_error,
+                    expected.magnetization,
+                    expected.magnetization_error,
+                ),
+                (
+                    "energy",
+                    record.energy,
+                    record.energy_error,
+                    expected.energy,
+                    expected.energy_error,
+                ),
+            ];
+            for (name, value, error, expected, expected_error) in observables {
+                assert!(
+                    (value - expected).abs() <= 4.0 * error.hypot(expected_error),
+                    "{} of {} at T = {}: {} +- {}, Metropolis gives {} +- {}",
+                    name,
+                    updater,
+                    temperature,
+                    value,
+                    error,
+                    expected,
+                    expected_error
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,290 @@
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+
+use crate::lattice::{get_adjacent_indices, Lattice};
+use crate::updater::Update;
+use crate::wolff::get_add_probability;
+
+const RIGHT_BOND: u8 = 1;
+const BOTTOM_BOND: u8 = 2;
+
+/// Number of lattice rows labelled together by a single parallel task.
+const STRIP_ROWS: usize = 8;
+
+/// Swendsen–Wang multi-cluster updates.
+///
+/// Bonds are activated row by row in parallel, each row drawing from its own
+/// random stream, and clusters are labelled in parallel on horizontal strips
+/// that are stitched together afterwards. The work split depends only on the
+/// lattice size, so results do not depend on the number of threads.
+pub struct SwendsenWang<S> {
+    add_probability: f64,
+    row_rngs: Vec<S>,
+    bonds: Vec<u8>,
+    parent: Vec<usize>,
+    labels: Vec<usize>,
+    flips: Vec<bool>,
+}
+
+impl<S: Rng + SeedableRng + Send> SwendsenWang<S> {
+    /// Creates the updater for lattices of the given size,
+    /// seeding the per-row random streams from `rng`.
+    pub fn new<R: Rng + ?Sized>(temperature: f64, lattice_size: usize, rng: &mut R) -> Self {
+        let sites = lattice_size * lattice_size;
+        SwendsenWang {
+            add_probability: get_add_probability(temperature),
+            row_rngs: (0..lattice_size)
+                .map(|_| S::seed_from_u64(rng.gen()))
+                .collect(),
+            bonds: vec![0; sites],
+            parent: vec![0; sites],
+            labels: vec![0; sites],
+            flips: vec![false; sites],
+        }
+    }
+
+    /// Decomposes the lattice into Fortuin–Kasteleyn clusters.
+    ///
+    /// Returns the cluster label of every site; the label is the smallest site index in the cluster.
+    pub fn label_clusters(&mut self, lattice: &Lattice) -> &[usize] {
+        self.activate_bonds(lattice);
+        self.label_strips(lattice.size());
+        self.stitch_strips(lattice.size());
+
+        let parent = &self.parent;
+        self.labels
+            .par_iter_mut()
+            .enumerate()
+            .for_each(|(index, label)| *label = get_root(parent, index));
+        &self.labels
+    }
+
+    /// Cluster labels computed by the last update.
+    pub fn labels(&self) -> &[usize] {
+        &self.labels
+    }
+
+    fn activate_bonds(&mut self, lattice: &Lattice) {
+        let size = lattice.size();
+        let spins = lattice.spins();
+        let add_probability = self.add_probability;
+        self.bonds
+            .par_chunks_mut(size)
+            .zip(self.row_rngs.par_iter_mut())
+            .enumerate()
+            .for_each(|(row, (row_bonds, rng))| {
+                for (column, bond) in row_bonds.iter_mut().enumerate() {
+                    let index = row * size + column;
+                    let [_, _, right, bottom] = get_adjacent_indices(index, size);
+                    *bond = 0;
+                    if spins[index] == spins[right] && rng.gen_bool(add_probability) {
+                        *bond |= RIGHT_BOND;
+                    }
+                    if spins[index] == spins[bottom] && rng.gen_bool(add_probability) {
+                        *bond |= BOTTOM_BOND;
+                    }
+                }
+            });
+    }
+
+    /// Labels clusters within each strip, ignoring bonds that leave the strip.
+    fn label_strips(&mut self, size: usize) {
+        let bonds = &self.bonds;
+        self.parent
+            .par_chunks_mut(STRIP_ROWS * size)
+            .enumerate()
+            .for_each(|(strip, parent)| {
+                let offset = strip * STRIP_ROWS * size;
+                parent
+                    .iter_mut()
+                    .enumerate()
+                    .for_each(|(i, p)| *p = offset + i);
+                let end = offset + parent.len();
+                for (index, bond) in (offset..end).zip(&bonds[offset..end]) {
+                    let [_, _, right, bottom] = get_adjacent_indices(index, size);
+                    if bond & RIGHT_BOND != 0 {
+                        union(parent, offset, index, right);
+                    }
+                    if bond & BOTTOM_BOND != 0 && bottom > index && bottom < end {
+                        union(parent, offset, index, bottom);
+                    }
+                }
+            });
+    }
+
+    /// Merges clusters across strip boundaries, including the periodic one.
+    fn stitch_strips(&mut self, size: usize) {
+        let sites = size * size;
+        let boundary_rows = (STRIP_ROWS - 1..size)
+            .step_by(STRIP_ROWS)
+            .chain(std::iter::once(size - 1));
+        for row in boundary_rows {
+            for index in row * size..(row + 1) * size {
+                if self.bonds[index] & BOTTOM_BOND != 0 {
+                    union(&mut self.parent, 0, index, (index + size) % sites);
+                }
+            }
+        }
+    }
+}
+
+impl<S: Rng + SeedableRng + Send> Update for SwendsenWang<S> {
+    /// Builds all clusters and flips each of them with probability 1/2.
+    fn sweep<R: Rng + ?Sized>(&mut self, lattice: &mut Lattice, rng: &mut R) -> i64 {
+        self.label_clusters(lattice);
+
+        for (index, flip) in self.flips.iter_mut().enumerate() {
+            if self.labels[index] == index {
+                *flip = rng.gen();
+            }
+        }
+        let flips = &self.flips;
+        let labels = &self.labels;
+        let size = lattice.size();
+        let spins = lattice.spins();
+        // only bonds between a flipped and an unflipped cluster change their energy
+        let total_change: i64 = (0..lattice.len())
+            .into_par_iter()
+            .map(|index| {
+                let [_, _, right, bottom] = get_adjacent_indices(index, size);
+                let flipped = flips[labels[index]];
+                [right, bottom]
+                    .iter()
+                    .filter(|neighbour| flips[labels[**neighbour]] != flipped)
+                    .map(|neighbour| 2 * (spins[index] * spins[*neighbour]) as i64)
+                    .sum::<i64>()
+            })
+            .sum();
+
+        lattice
+            .spins_mut()
+            .par_iter_mut()
+            .zip(self.labels.par_iter())
+            .for_each(|(spin, label)| {
+                if flips[*label] {
+                    *spin = -*spin;
+                }
+            });
+        total_change
+    }
+}
+
+/// Finds the root of `index` in a union-find forest stored at `parent[offset..]`,
+/// halving the path on the way.
+fn find(parent: &mut [usize], offset: usize, mut index: usize) -> usize {
+    while parent[index - offset] != index {
+        let grandparent = parent[parent[index - offset] - offset];
+        parent[index - offset] = grandparent;
+        index = grandparent;
+    }
+    index
+}
+
+/// Joins the trees of `a` and `b`, keeping the smaller index as the root.
+fn union(parent: &mut [usize], offset: usize, a: usize, b: usize) {
+    let (root_a, root_b) = (find(parent, offset, a), find(parent, offset, b));
+    if root_a != root_b {
+        parent[root_a.max(root_b) - offset] = root_a.min(root_b);
+    }
+}
+
+fn get_root(parent: &[usize], mut index: usize) -> usize {
+    while parent[index] != index {
+        index = parent[index];
+    }
+    index
+}
+
+/// Sizes of all clusters, given the labels returned by [`SwendsenWang::label_clusters`].
+pub fn get_cluster_sizes(labels: &[usize]) -> Vec<usize> {
+    let mut sizes = vec![0; labels.len()];
+    labels.iter().for_each(|label| sizes[*label] += 1);
+    sizes.retain(|size| *size > 0);
+    sizes
+}
+
+/// Fraction of the sites belonging to the largest cluster.
+pub fn get_largest_cluster_fraction(labels: &[usize]) -> f64 {
+    let largest = get_cluster_sizes(labels).into_iter().max().unwrap_or(0);
+    largest as f64 / labels.len() as f64
+}
+
+/// Average size of the cluster a randomly chosen site belongs to, `sum(|C|^2) / N`.
+pub fn get_mean_cluster_size(labels: &[usize]) -> f64 {
+    let squares: usize = get_cluster_sizes(labels)
+        .iter()
+        .map(|size| size * size)
+        .sum();
+    squares as f64 / labels.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::rng::Xoshiro256PlusPlus;
+    use crate::sweep::tests::assert_agrees_with_metropolis;
+    use crate::updater::Updater;
+
+    /// Labels the clusters formed by the active bonds breadth-first, one cluster at a time.
+    fn label_breadth_first(bonds: &[u8], size: usize) -> Vec<usize> {
+        let sites = size * size;
+        let mut neighbours = vec![Vec::new(); sites];
+        for (index, bond) in bonds.iter().enumerate() {
+            let [_, _, right, bottom] = get_adjacent_indices(index, size);
+            if bond & RIGHT_BOND != 0 {
+                neighbours[index].push(right);
+                neighbours[right].push(index);
+            }
+            if bond & BOTTOM_BOND != 0 {
+                neighbours[index].push(bottom);
+                neighbours[bottom].push(index);
+            }
+        }
+
+        let mut labels = vec![usize::MAX; sites];
+        for start in 0..sites {
+            if labels[start] != usize::MAX {
+                continue;
+            }
+            // sites are visited in increasing order, so `start` is the smallest index
+            labels[start] = start;
+            let mut queue = VecDeque::from([start]);
+            while let Some(index) = queue.pop_front() {
+                for neighbour in &neighbours[index] {
+                    if labels[*neighbour] == usize::MAX {
+                        labels[*neighbour] = start;
+                        queue.push_back(*neighbour);
+                    }
+                }
+            }
+        }
+        labels
+    }
+
+    #[test]
+    fn labels_match_breadth_first_search() {
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(3);
+        // sizes that split into whole strips, a partial last strip and a single strip
+        for size in [16, 20, 5] {
+            for temperature in [1.5, 2.27, 3.5] {
+                let mut lattice = Lattice::random(size, &mut rng);
+                let mut updater =
+                    SwendsenWang::<Xoshiro256PlusPlus>::new(temperature, size, &mut rng);
+                for _ in 0..5 {
+                    let labels = updater.label_clusters(&lattice).to_vec();
+                    assert_eq!(labels, label_breadth_first(&updater.bonds, size));
+                    updater.sweep(&mut lattice, &mut rng);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn agrees_with_metropolis() {
+        assert_agrees_with_metropolis(Updater::SwendsenWang);
+    }
+}
@@ -0,0 +1,289 @@
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+
+use crate::config::Sweep;
+use crate::lattice::Lattice;
+use crate::observables::{get_energy, get_magnetization, TimeSeries};
+use crate::results::Record;
+use crate::rng::WithRng;
+use crate::sweep::{initial_lattice, task_seed};
+use crate::updater::{AnyUpdater, Update};
+
+/// Acceptance rate of configuration swaps between two neighbouring temperatures.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SwapRate {
+    pub lattice_size: usize,
+    pub lower_temperature: f64,
+    pub upper_temperature: f64,
+    /// Swaps attempted, and accepted, after thermalization.
+    pub attempts: u32,
+    pub accepted: u32,
+}
+
+impl SwapRate {
+    /// Fraction of the attempted swaps that were accepted, or 0 without attempts.
+    pub fn rate(&self) -> f64 {
+        if self.attempts == 0 {
+            return 0.0;
+        }
+        self.accepted as f64 / self.attempts as f64
+    }
+}
+
+/// Chain running at a fixed temperature; configurations move between replicas on swaps.
+struct Replica<S> {
+    temperature: f64,
+    lattice: Lattice,
+    /// Total energy of `lattice`, followed through the energy changes of the updates.
+    energy: i64,
+    updater: AnyUpdater<S>,
+    rng: S,
+    series: TimeSeries,
+}
+
+impl<S: Rng + SeedableRng + Send> Replica<S> {
+    /// Performs the sweeps numbered `steps`, measuring when `measure` says so.
+    fn advance(&mut self, steps: std::ops::Range<u32>, measure: impl Fn(u32) -> bool) {
+        for step in steps {
+            self.energy += self.updater.sweep(&mut self.lattice, &mut self.rng);
+            if measure(step) {
+                self.series
+                    .add(get_magnetization(&self.lattice), self.energy);
+            }
+        }
+    }
+}
+
+/// Runs one replica per temperature of the sweep for every lattice size, attempting
+/// configuration swaps between neighbouring temperatures every `swap_interval` sweeps.
+///
+/// `on_done` is called once per lattice size. Records are returned in the same order as
+/// [`crate::sweep::run_sweep`] returns them, followed by the swap acceptance rates.
+/// Fails for updaters that do not act on a single lattice.
+pub fn run_tempering_sweep<F>(
+    sweep: &Sweep,
+    swap_interval: u32,
+    on_done: F,
+) -> Result<(Vec<Record>, Vec<SwapRate>), String>
+where
+    F: Fn() + Sync,
+{
+    let results: Vec<(Vec<Record>, Vec<SwapRate>)> = sweep
+        .sizes
+        .par_iter()
+        .map(|lattice_size| {
+            let result = sweep.rng.dispatch(TemperingTask {
+                sweep,
+                lattice_size: *lattice_size,
+                swap_interval,
+            });
+            on_done();
+            result
+        })
+        .collect::<Result<_, _>>()?;
+
+    let mut records = Vec::new();
+    let mut swap_rates = Vec::new();
+    for (size_records, size_swap_rates) in results {
+        records.extend(size_records);
+        swap_rates.extend(size_swap_rates);
+    }
+    Ok((records, swap_rates))
+}
+
+/// Parallel tempering of a single lattice size of the sweep.
+struct TemperingTask<'a> {
+    sweep: &'a Sweep,
+    lattice_size: usize,
+    swap_interval: u32,
+}
+
+impl WithRng for TemperingTask<'_> {
+    type Output = Result<(Vec<Record>, Vec<SwapRate>), String>;
+
+    fn run<S: Rng + SeedableRng + Send>(self) -> Self::Output {
+        temper::<S>(self.sweep, self.lattice_size, self.swap_interval)
+    }
+}
+
+/// Parallel tempering of a single lattice size.
+pub fn temper<S: Rng + SeedableRng + Send>(
+    sweep: &Sweep,
+    lattice_size: usize,
+    swap_interval: u32,
+) -> Result<(Vec<Record>, Vec<SwapRate>), String> {
+    // replicas are ordered by temperature, so that swaps happen between neighbours
+    let mut order: Vec<usize> = (0..sweep.temperatures.len()).collect();
+    order.sort_by(|a, b| sweep.temperatures[*a].total_cmp(&sweep.temperatures[*b]));
+
+    let mut replicas: Vec<Replica<S>> = order
+        .iter()
+        .map(|i| {
+            let temperature = sweep.temperatures[*i];
+            let mut rng = S::seed_from_u64(task_seed(sweep.seed, lattice_size, temperature));
+            let lattice = initial_lattice(sweep, lattice_size, &mut rng);
+            Ok(Replica {
+                temperature,
+                energy: get_energy(&lattice),
+                lattice,
+                updater: AnyUpdater::new(sweep.updater, temperature, lattice_size, &mut rng)?,
+                rng,
+                series: TimeSeries::new(sweep.initial_steps, sweep.magn_calc_step, 1),
+            })
+        })
+        .collect::<Result<_, String>>()?;
+    // temperatures are positive, so this stream differs from the streams of all replicas
+    let mut swap_rng = S::seed_from_u64(task_seed(sweep.seed, lattice_size, -1.0));
+    let mut swap_rates: Vec<SwapRate> = replicas
+        .windows(2)
+        .map(|pair| SwapRate {
+            lattice_size,
+            lower_temperature: pair[0].temperature,
+            upper_temperature: pair[1].temperature,
+            attempts: 0,
+            accepted: 0,
+        })
+        .collect();
+
+    let total_steps = sweep.initial_steps + sweep.later_steps;
+    let mut step = 0;
+    let mut round = 0;
+    while step < total_steps {
+        let end = (step + swap_interval).min(total_steps);
+        replicas.par_iter_mut().for_each(|replica| {
+            replica.advance(step..end, |s| {
+                s >= sweep.initial_steps
+                    && (s - sweep.initial_steps).is_multiple_of(sweep.magn_calc_step)
+            })
+        });
+        step = end;
+        // swaps during thermalization are not counted, the replicas are not in equilibrium yet
+        let measuring = step >= sweep.initial_steps;
+
+        // alternate between even and odd pairs, so every pair is attempted every other round
+        for lower in (round % 2..replicas.len().saturating_sub(1)).step_by(2) {
+            let upper = lower + 1;
+            let exponent = (1.0 / replicas[lower].temperature - 1.0 / replicas[upper].temperature)
+                * (replicas[lower].energy - replicas[upper].energy) as f64;
+            let accepted = exponent >= 0.0 || swap_rng.gen::<f64>() < exponent.exp();
+            if measuring {
+                swap_rates[lower].attempts += 1;
+                swap_rates[lower].accepted += accepted as u32;
+            }
+            if accepted {
+                let (left, right) = replicas.split_at_mut(upper);
+                std::mem::swap(&mut left[lower].lattice, &mut right[0].lattice);
+                std::mem::swap(&mut left[lower].energy, &mut right[0].energy);
+            }
+        }
+        round += 1;
+    }
+
+    let mut records: Vec<Record> = replicas
+        .iter_mut()
+        .map(|replica| {
+            replica.series.record(
+                lattice_size,
+                replica.temperature,
+                sweep.error_method,
+                &mut replica.rng,
+            )
+        })
+        .collect();
+    // restore the order of the sweep's temperatures
+    let mut sorted = vec![None; records.len()];
+    for (record, i) in records.drain(..).zip(order) {
+        sorted[i] = Some(record);
+    }
+    Ok((sorted.into_iter().flatten().collect(), swap_rates))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TargetObservable;
+    use crate::refinement::RefinementObservable;
+    use crate::rng::{RngKind, Xoshiro256PlusPlus};
+    use crate::statistics::ErrorMethod;
+    use crate::sweep::run_sweep;
+    use crate::updater::Updater;
+
+    fn get_sweep() -> Sweep {
+        Sweep {
+            sizes: vec![8],
+            temperatures: vec![2.6, 2.0, 2.3],
+            initial_steps: 1_000,
+            auto_equilibration: false,
+            later_steps: 20_000,
+            magn_calc_step: 1,
+            target_error: None,
+            target_observable: TargetObservable::default(),
+            seed: 5,
+            rng: RngKind::Xoshiro,
+            updater: Updater::Metropolis,
+            initial_magnetization: Some(1.0),
+            swap_interval: None,
+            error_method: ErrorMethod::default(),
+            refine_rounds: 0,
+            refine_points: 0,
+            refine_observable: RefinementObservable::default(),
+            save_series: false,
+        }
+    }
+
+    #[test]
+    fn agrees_with_independent_chains() {
+        let sweep = get_sweep();
+        let (records, _) = temper::<Xoshiro256PlusPlus>(&sweep, 8, 10).unwrap();
+        let expected_records = run_sweep(&sweep, |_, _| {});
+        for (record, expected) in records.iter().zip(&expected_records) {
+            assert_eq!(record.temperature, expected.temperature);
+            let observables = [
+                (
+                    "magnetization",
+                    record.magnetization,
+                    record.magnetization_error,
+                    expected.magnetization,
+                    expected.magnetization_error,
+                ),
+                (
+                    "energy",
+                    record.energy,
+                    record.energy_error,
+                    expected.energy,
+                    expected.energy_error,
+                ),
+            ];
+            for (name, value, error, expected_value, expected_error) in observables {
+                assert!(
+                    (value - expected_value).abs() <= 4.0 * error.hypot(expected_error),
+                    "{} at T = {}: {} +- {}, independent chains give {} +- {}",
+                    name,
+                    record.temperature,
+                    value,
+                    error,
+                    expected_value,
+                    expected_error
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn counts_swaps_after_thermalization() {
+        let (_, swap_rates) = temper::<Xoshiro256PlusPlus>(&get_sweep(), 8, 10).unwrap();
+        // 2100 rounds of 10 sweeps, of which the 2001 from the one ending at sweep 1000 on
+        // count; even rounds attempt the lower pair, odd rounds the upper one
+        let attempts: Vec<u32> = swap_rates.iter().map(|rate| rate.attempts).collect();
+        assert_eq!(attempts, vec![1000, 1001]);
+        for rate in &swap_rates {
+            assert!(
+                (0.0..=1.0).contains(&rate.rate()) && rate.accepted > 0,
+                "swap rate {} between T = {} and {}",
+                rate.rate(),
+                rate.lower_temperature,
+                rate.upper_temperature
+            );
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use std::fmt;
+
+use clap::ValueEnum;
+use rand::{Rng, SeedableRng};
+use serde::Deserialize;
+
+use crate::checkerboard::Checkerboard;
+use crate::glauber::Glauber;
+use crate::kawasaki::Kawasaki;
+use crate::lattice::Lattice;
+use crate::metropolis::Metropolis;
+use crate::swendsen_wang::SwendsenWang;
+use crate::wolff::Wolff;
+
+/// Algorithm moving the lattice through configuration space.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Updater {
+    /// Single-spin flips with Metropolis acceptance
+    #[default]
+    Metropolis,
+    /// Single-spin flips at random sites with heat-bath (Glauber) acceptance
+    #[value(alias = "heat-bath")]
+    #[serde(alias = "heat-bath")]
+    Glauber,
+    /// Single-spin Metropolis flips, updating each checkerboard sublattice in parallel
+    Checkerboard,
+    /// Metropolis flips of 64 bit-packed replicas at once, pooling their measurements
+    MultiSpin,
+    /// Wolff single-cluster flips
+    Wolff,
+    /// Swendsen–Wang multi-cluster flips
+    SwendsenWang,
+    /// Nearest-neighbour spin exchanges, conserving the magnetization
+    Kawasaki,
+}
+
+impl Updater {
+    /// Whether the updater only works on lattices of even size.
+    pub fn needs_even_size(&self) -> bool {
+        *self == Updater::Checkerboard
+    }
+
+    /// Whether the updater acts on a single [`Lattice`], as opposed to packed replicas.
+    pub fn is_single_lattice(&self) -> bool {
+        *self != Updater::MultiSpin
+    }
+}
+
+impl fmt::Display for Updater {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.to_possible_value().unwrap().get_name())
+    }
+}
+
+/// A Monte Carlo update rule.
+pub trait Update {
+    /// Advances the lattice by one unit of Monte Carlo time, i.e. on average one
+    /// update attempt (or flip, for cluster algorithms) per spin.
+    ///
+    /// Returns the change of the total energy, so that callers can follow the energy
+    /// without recomputing it from scratch.
+    fn sweep<R: Rng + ?Sized>(&mut self, lattice: &mut Lattice, rng: &mut R) -> i64;
+}
+
+/// Any of the update rules, chosen at runtime.
+///
+/// `S` is the generator type of the per-row random streams used by the parallel updaters.
+pub enum AnyUpdater<S> {
+    Metropolis(Metropolis),
+    Glauber(Glauber),
+    Checkerboard(Checkerboard<S>),
+    Wolff(Wolff),
+    SwendsenWang(SwendsenWang<S>),
+    Kawasaki(Kawasaki),
+}
+
+impl<S: Rng + SeedableRng + Send> AnyUpdater<S> {
+    /// Builds the chosen update rule for the given temperature and lattice size,
+    /// seeding any auxiliary random streams from `rng`.
+    ///
+    /// Fails for [`Updater::MultiSpin`], which does not act on a [`Lattice`], and for odd
+    /// sizes with updaters that need an even one.
+    pub fn new<R: Rng + ?Sized>(
+        updater: Updater,
+        temperature: f64,
+        lattice_size: usize,
+        rng: &mut R,
+    ) -> Result<Self, String> {
+        if updater.needs_even_size() && !lattice_size.is_multiple_of(2) {
+            return Err(format!(
+                "the {} updater needs an even lattice size, got {}",
+                updater, lattice_size
+            ));
+        }
+        let updater = match updater {
+            Updater::Metropolis => {
+                AnyUpdater::Metropolis(Metropolis::new(temperature, lattice_size))
+            }
+            Updater::Glauber => AnyUpdater::Glauber(Glauber::new(temperature, lattice_size)),
+            Updater::Checkerboard => {
+                AnyUpdater::Checkerboard(Checkerboard::new(temperature, lattice_size, rng))
+            }
+            Updater::Wolff => AnyUpdater::Wolff(Wolff::new(temperature)),
+            Updater::SwendsenWang => {
+                AnyUpdater::SwendsenWang(SwendsenWang::new(temperature, lattice_size, rng))
+            }
+            Updater::Kawasaki => AnyUpdater::Kawasaki(Kawasaki::new(temperature)),
+            Updater::MultiSpin => {
+                return Err(format!(
+                    "the {} updater acts on packed replicas, not a single lattice",
+                    updater
+                ))
+            }
+        };
+        Ok(updater)
+    }
+}
+
+impl<S: Rng + SeedableRng + Send> Update for AnyUpdater<S> {
+    fn sweep<R: Rng + ?Sized>(&mut self, lattice: &mut Lattice, rng: &mut R) -> i64 {
+        match self {
+            AnyUpdater::Metropolis(updater) => updater.sweep(lattice, rng),
+            AnyUpdater::Glauber(updater) => updater.sweep(lattice, rng),
+            AnyUpdater::Checkerboard(updater) => updater.sweep(lattice, rng),
+            AnyUpdater::Wolff(updater) => updater.sweep(lattice, rng),
+            AnyUpdater::SwendsenWang(updater) => updater.sweep(lattice, rng),
+            AnyUpdater::Kawasaki(updater) => updater.sweep(lattice, rng),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::observables::get_energy;
+    use crate::rng::Xoshiro256PlusPlus;
+
+    #[test]
+    fn energy_changes_add_up() {
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(5);
+        for updater in Updater::value_variants() {
+            if !updater.is_single_lattice() {
+                continue;
+            }
+            for temperature in [1.5, 2.27, 3.5] {
+                let mut lattice = Lattice::random(10, &mut rng);
+                let mut energy = get_energy(&lattice);
+                let mut update =
+                    AnyUpdater::<Xoshiro256PlusPlus>::new(*updater, temperature, 10, &mut rng)
+                        .unwrap();
+                for _ in 0..100 {
+                    energy += update.sweep(&mut lattice, &mut rng);
+                }
+                assert_eq!(
+                    energy,
+                    get_energy(&lattice),
+                    "{} at T = {}",
+                    updater,
+                    temperature
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_odd_sizes_for_checkerboard() {
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(6);
+        assert!(
+            AnyUpdater::<Xoshiro256PlusPlus>::new(Updater::Checkerboard, 2.0, 9, &mut rng).is_err()
+        );
+        assert!(
+            AnyUpdater::<Xoshiro256PlusPlus>::new(Updater::Metropolis, 2.0, 9, &mut rng).is_ok()
+        );
+    }
+}
@@ -0,0 +1,296 @@
+use rand::{Rng, SeedableRng};
+
+use crate::lattice::Lattice;
+use crate::observables::get_energy;
+use crate::rng::{RngKind, WithRng};
+
+/// Wang–Landau estimation of the density of states g(E) of a single lattice size, with the 1/t
+/// refinement of the modification factor.
+pub struct WangLandau {
+    pub lattice_size: usize,
+    /// Every visited energy needs at least `flatness` times the mean histogram count
+    /// before the modification factor is reduced.
+    pub flatness: f64,
+    /// The simulation stops once `ln f` drops below this value.
+    pub final_modification: f64,
+    /// Sweeps between checks of the histogram flatness.
+    pub check_interval: u32,
+    pub seed: u64,
+    pub rng: RngKind,
+}
+
+/// Logarithm of the density of states, normalized so that g(E_min) = 2.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DensityOfStates {
+    pub lattice_size: usize,
+    /// Total energies of the visited levels, in increasing order.
+    pub energies: Vec<i64>,
+    pub log_g: Vec<f64>,
+}
+
+/// Canonical thermodynamics per spin at a single temperature.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Thermodynamics {
+    pub temperature: f64,
+    pub internal_energy: f64,
+    pub free_energy: f64,
+    pub entropy: f64,
+    pub specific_heat: f64,
+}
+
+impl WangLandau {
+    /// Settings recorded in the header of the output files.
+    pub fn metadata(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("seed", self.seed.to_string()),
+            ("rng", self.rng.to_string()),
+            ("lattice_size", self.lattice_size.to_string()),
+            ("flatness", self.flatness.to_string()),
+            ("final_modification", self.final_modification.to_string()),
+            ("check_interval", self.check_interval.to_string()),
+        ]
+    }
+
+    /// Number of times the modification factor is halved before the simulation stops.
+    pub fn stages(&self) -> usize {
+        (1.0 / self.final_modification).log2().ceil().max(0.0) as usize
+    }
+
+    /// Runs the simulation, calling `on_stage` every time `ln f` has halved, [`WangLandau::stages`]
+    /// times in total.
+    ///
+    /// Fails when no energy was visited, i.e. when `final_modification` is not below the
+    /// initial `ln f = 1`.
+    pub fn run<F: Fn(f64)>(&self, on_stage: F) -> Result<DensityOfStates, String> {
+        self.rng.dispatch(WangLandauTask {
+            wang_landau: self,
+            on_stage,
+        })
+    }
+
+    /// Same as [`WangLandau::run`], drawing random numbers from the given generator.
+    pub fn run_with<R: Rng, F: Fn(f64)>(
+        &self,
+        rng: &mut R,
+        on_stage: F,
+    ) -> Result<DensityOfStates, String> {
+        let mut lattice = Lattice::random(self.lattice_size, rng);
+        let sites = lattice.len() as i64;
+        // levels are 4 apart, from -2N to 2N
+        let level = |energy: i64| ((energy + 2 * sites) / 4) as usize;
+        let levels = lattice.len() + 1;
+
+        let mut log_g = vec![0.0f64; levels];
+        let mut histogram = vec![0u64; levels];
+        let mut visited = vec![false; levels];
+        let mut current = level(get_energy(&lattice));
+        let mut modification = 1.0;
+        // halving ln f on flat histograms alone freezes the error of ln g, so once ln f falls
+        // below 1/t (t counting sweeps) it follows 1/t instead (Belardinelli and Pereyra)
+        let mut sweeps = 0u64;
+        let mut inverse_time = false;
+        let mut reported = 1.0;
+
+        while modification >= self.final_modification {
+            for _ in 0..self.check_interval {
+                sweeps += 1;
+                if inverse_time {
+                    modification = 1.0 / sweeps as f64;
+                }
+                // sites are picked at random: in sequential sweeps a walker whose every flip is
+                // accepted outright can cycle between two energies forever
+                for _ in 0..lattice.len() {
+                    let index = rng.gen_range(0..lattice.len());
+                    let spin = lattice.spins()[index];
+                    let energy_change = 2 * (spin * lattice.neighbour_sum(index)) as i64;
+                    let proposed = (current as i64 + energy_change / 4) as usize;
+                    let log_ratio = log_g[current] - log_g[proposed];
+                    if log_ratio >= 0.0 || rng.gen::<f64>() < log_ratio.exp() {
+                        lattice.spins_mut()[index] = -spin;
+                        current = proposed;
+                    }
+                    log_g[current] += modification;
+                    histogram[current] += 1;
+                    visited[current] = true;
+                }
+            }
+
+            if !inverse_time && is_flat(&histogram, &visited, self.flatness) {
+                modification /= 2.0;
+                histogram.iter_mut().for_each(|count| *count = 0);
+                inverse_time = modification < 1.0 / sweeps as f64;
+            }
+            // under 1/t the last stage may end before ln f has halved once more
+            while modification <= reported / 2.0
+                || (modification < self.final_modification && reported >= self.final_modification)
+            {
+                reported = (reported / 2.0).max(modification);
+                on_stage(reported);
+            }
+        }
+
+        let (energies, mut log_g): (Vec<i64>, Vec<f64>) = (0..levels)
+            .filter(|level| visited[*level])
+            .map(|level| (4 * level as i64 - 2 * sites, log_g[level]))
+            .unzip();
+        if log_g.is_empty() {
+            return Err(format!(
+                "no energy was visited; final_modification ({}) must be below 1",
+                self.final_modification
+            ));
+        }
+        // the ground state is twofold degenerate
+        let shift = 2f64.ln() - log_g[0];
+        log_g.iter_mut().for_each(|value| *value += shift);
+        Ok(DensityOfStates {
+            lattice_size: self.lattice_size,
+            energies,
+            log_g,
+        })
+    }
+}
+
+/// Wang–Landau simulation reporting its progress to `on_stage`.
+struct WangLandauTask<'a, F> {
+    wang_landau: &'a WangLandau,
+    on_stage: F,
+}
+
+impl<F: Fn(f64)> WithRng for WangLandauTask<'_, F> {
+    type Output = Result<DensityOfStates, String>;
+
+    fn run<R: Rng + SeedableRng + Send>(self) -> Self::Output {
+        let wang_landau = self.wang_landau;
+        wang_landau.run_with(&mut R::seed_from_u64(wang_landau.seed), self.on_stage)
+    }
+}
+
+fn is_flat(histogram: &[u64], visited: &[bool], flatness: f64) -> bool {
+    let counts: Vec<u64> = histogram
+        .iter()
+        .zip(visited)
+        .filter(|(_, visited)| **visited)
+        .map(|(count, _)| *count)
+        .collect();
+    let mean = counts.iter().sum::<u64>() as f64 / counts.len() as f64;
+    counts.iter().all(|count| *count as f64 >= flatness * mean)
+}
+
+impl DensityOfStates {
+    /// Canonical averages at `temperature`, computed from the density of states.
+    pub fn thermodynamics(&self, temperature: f64) -> Thermodynamics {
+        let sites = (self.lattice_size * self.lattice_size) as f64;
+        let exponents: Vec<f64> = self
+            .energies
+            .iter()
+            .zip(&self.log_g)
+            .map(|(energy, log_g)| log_g - *energy as f64 / temperature)
+            .collect();
+        // subtract the largest exponent to keep the Boltzmann weights finite
+        let max = exponents.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        let weights: Vec<f64> = exponents.iter().map(|x| (x - max).exp()).collect();
+        let partition: f64 = weights.iter().sum();
+        let (energy_sum, energy_sqr_sum) =
+            self.energies
+                .iter()
+                .zip(&weights)
+                .fold((0.0, 0.0), |acc, (energy, weight)| {
+                    let energy = *energy as f64;
+                    (acc.0 + weight * energy, acc.1 + weight * energy * energy)
+                });
+        let energy = energy_sum / partition;
+        let energy_sqr = energy_sqr_sum / partition;
+
+        let free_energy = -temperature * (partition.ln() + max);
+        Thermodynamics {
+            temperature,
+            internal_energy: energy / sites,
+            free_energy: free_energy / sites,
+            entropy: (energy - free_energy) / (temperature * sites),
+            specific_heat: (energy_sqr - energy * energy) / (sites * temperature * temperature),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::rng::Xoshiro256PlusPlus;
+
+    /// ln g(E) of every energy of a `size` x `size` lattice, counted over all states.
+    fn get_exact_log_g(size: usize) -> Vec<(i64, f64)> {
+        let sites = size * size;
+        let mut counts = BTreeMap::new();
+        for state in 0..1u64 << sites {
+            let spins = (0..sites)
+                .map(|index| if state >> index & 1 == 1 { 1 } else { -1 })
+                .collect();
+            *counts
+                .entry(get_energy(&Lattice::from_spins(size, spins)))
+                .or_insert(0u64) += 1;
+        }
+        counts
+            .into_iter()
+            .map(|(energy, count)| (energy, (count as f64).ln()))
+            .collect()
+    }
+
+    #[test]
+    fn recovers_exact_density_of_states() {
+        let wang_landau = WangLandau {
+            lattice_size: 4,
+            flatness: 0.8,
+            final_modification: 1e-6,
+            check_interval: 1000,
+            seed: 1,
+            rng: RngKind::Xoshiro,
+        };
+        let stages = std::cell::Cell::new(0);
+        let density_of_states = wang_landau
+            .run_with(&mut Xoshiro256PlusPlus::seed_from_u64(1), |_| {
+                stages.set(stages.get() + 1)
+            })
+            .unwrap();
+        assert_eq!(stages.get(), wang_landau.stages());
+
+        let exact = get_exact_log_g(4);
+        let energies: Vec<i64> = exact.iter().map(|(energy, _)| *energy).collect();
+        assert_eq!(density_of_states.energies, energies);
+        // with ln f followed down to 1e-6 every ln g(E) is accurate to a few hundredths
+        for ((energy, log_g), (_, expected)) in density_of_states
+            .energies
+            .iter()
+            .zip(&density_of_states.log_g)
+            .zip(&exact)
+        {
+            assert!(
+                (log_g - expected).abs() < 0.05,
+                "ln g({}) = {} instead of {}",
+                energy,
+                log_g,
+                expected
+            );
+        }
+        let max = density_of_states
+            .log_g
+            .iter()
+            .cloned()
+            .fold(f64::MIN, f64::max);
+        let log_total = max
+            + density_of_states
+                .log_g
+                .iter()
+                .map(|log_g| (log_g - max).exp())
+                .sum::<f64>()
+                .ln();
+        let expected = 16.0 * 2f64.ln();
+        assert!(
+            (log_total - expected).abs() < 0.05,
+            "ln of the number of states is {} instead of {}",
+            log_total,
+            expected
+        );
+    }
+}
@@ -0,0 +1,111 @@
+use rand::Rng;
+
+use crate::lattice::Lattice;
+use crate::updater::Update;
+
+/// Probability of adding an aligned neighbour to a Wolff cluster.
+pub fn get_add_probability(temp: f64) -> f64 {
+    1.0 - (-2.0 / temp).exp()
+}
+
+/// Grows a cluster from a random seed site and flips it, returning the number of flipped spins
+/// and the change of the total energy.
+///
+/// `stack` is scratch space reused between calls to avoid reallocating it for every cluster.
+pub fn flip_cluster<R: Rng + ?Sized>(
+    lattice: &mut Lattice,
+    add_probability: f64,
+    stack: &mut Vec<usize>,
+    rng: &mut R,
+) -> (usize, i64) {
+    let seed = rng.gen_range(0..lattice.len());
+    let cluster_spin = lattice.spins()[seed];
+
+    // spins are flipped as soon as they join the cluster,
+    // so a flipped spin is never considered again;
+    // the energy change of the whole cluster adds up from these single flips
+    let mut energy_change = flip_spin(lattice, seed);
+    stack.clear();
+    stack.push(seed);
+    let mut cluster_size = 1;
+
+    while let Some(index) = stack.pop() {
+        for neighbour in lattice.adjacent(index).iter() {
+            if lattice.spins()[*neighbour] == cluster_spin && rng.gen_bool(add_probability) {
+                energy_change += flip_spin(lattice, *neighbour);
+                stack.push(*neighbour);
+                cluster_size += 1;
+            }
+        }
+    }
+    (cluster_size, energy_change)
+}
+
+/// Flips a single spin, returning the change of the total energy.
+fn flip_spin(lattice: &mut Lattice, index: usize) -> i64 {
+    let spin = lattice.spins()[index];
+    lattice.spins_mut()[index] = -spin;
+    2 * (spin * lattice.neighbour_sum(index)) as i64
+}
+
+/// Wolff cluster updates.
+pub struct Wolff {
+    add_probability: f64,
+    stack: Vec<usize>,
+    clusters: u64,
+    flipped: u64,
+}
+
+impl Wolff {
+    pub fn new(temperature: f64) -> Self {
+        Wolff {
+            add_probability: get_add_probability(temperature),
+            stack: Vec::new(),
+            clusters: 0,
+            flipped: 0,
+        }
+    }
+
+    /// Average number of spins flipped per cluster so far.
+    pub fn mean_cluster_size(&self) -> f64 {
+        self.flipped as f64 / self.clusters as f64
+    }
+}
+
+impl Update for Wolff {
+    /// Flips as many clusters as needed to flip, on average, every spin of the lattice once,
+    /// which makes one call comparable to a single Metropolis sweep.
+    ///
+    /// The number of clusters is derived from the mean cluster size of earlier calls rather
+    /// than from the clusters flipped in this one, as stopping once enough spins have been
+    /// flipped would bias the measurements taken after the sweep.
+    fn sweep<R: Rng + ?Sized>(&mut self, lattice: &mut Lattice, rng: &mut R) -> i64 {
+        let clusters = if self.clusters == 0 {
+            1
+        } else {
+            (lattice.len() as f64 / self.mean_cluster_size())
+                .round()
+                .max(1.0) as u64
+        };
+        let mut total_change = 0;
+        for _ in 0..clusters {
+            let (size, energy_change) =
+                flip_cluster(lattice, self.add_probability, &mut self.stack, rng);
+            self.flipped += size as u64;
+            total_change += energy_change;
+        }
+        self.clusters += clusters;
+        total_change
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sweep::tests::assert_agrees_with_metropolis;
+    use crate::updater::Updater;
+
+    #[test]
+    fn agrees_with_metropolis() {
+        assert_agrees_with_metropolis(Updater::Wolff);
+    }
+}